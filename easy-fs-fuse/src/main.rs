@@ -0,0 +1,108 @@
+//! Pack user apps into an easy-fs disk image
+//!
+//! Every file in the source directory is copied into the root directory of a
+//! fresh filesystem, named after the file without its extension, so that
+//! `../user/build/elf/hello.elf` is exec'ed as `hello`:
+//!
+//! ```text
+//! easy-fs-fuse --source ../user/build/elf --image ../os/target/fs.img
+//! ```
+//!
+//! The image is then attached to QEMU as a virtio-blk device, where the
+//! kernel finds the apps.
+
+use clap::{App, Arg};
+use easy_fs::{BlockDevice, EasyFileSystem, BLOCK_SZ};
+use std::fs::{read, read_dir, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// size of the image, in blocks
+const TOTAL_BLOCKS: u32 = 16 * 2048;
+/// blocks of inode bitmap, enough for 4096 inodes
+const INODE_BITMAP_BLOCKS: u32 = 1;
+
+/// A file on the host, used as a disk
+struct BlockFile(Mutex<File>);
+
+impl BlockDevice for BlockFile {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64))
+            .expect("Error when seeking!");
+        file.read_exact(buf).expect("Error when reading!");
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64))
+            .expect("Error when seeking!");
+        file.write_all(buf).expect("Error when writing!");
+    }
+}
+
+fn main() {
+    let matches = App::new("EasyFileSystem packer")
+        .arg(
+            Arg::with_name("source")
+                .short("s")
+                .long("source")
+                .takes_value(true)
+                .required(true)
+                .help("Directory of the user apps"),
+        )
+        .arg(
+            Arg::with_name("image")
+                .short("i")
+                .long("image")
+                .takes_value(true)
+                .required(true)
+                .help("Path of the disk image to create"),
+        )
+        .get_matches();
+    let source = matches.value_of("source").unwrap();
+    let image = matches.value_of("image").unwrap();
+    if let Err(err) = pack(Path::new(source), Path::new(image)) {
+        eprintln!("easy-fs-fuse: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Create `image` and copy every file in `source` into it
+fn pack(source: &Path, image: &Path) -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(image)?;
+        f.set_len((TOTAL_BLOCKS as usize * BLOCK_SZ) as u64)?;
+        f
+    })));
+    let efs = EasyFileSystem::create(block_file, TOTAL_BLOCKS, INODE_BITMAP_BLOCKS);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let mut apps: Vec<_> = read_dir(source)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    apps.sort();
+    for path in apps.iter().filter(|path| path.is_file()) {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let data = read(path)?;
+        let inode = root_inode.create(&name).map_err(|err| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("cannot create {}: {:?}", name, err),
+            )
+        })?;
+        inode.write_at(0, &data).map_err(|err| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("cannot write {}: {:?}", name, err),
+            )
+        })?;
+        println!("{}: {} bytes", name, data.len());
+    }
+    Ok(())
+}
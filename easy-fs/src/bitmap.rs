@@ -0,0 +1,66 @@
+//! Allocation bitmaps
+
+use super::{get_block_cache, BlockDevice, BLOCK_SZ};
+use alloc::sync::Arc;
+
+/// bits in a block, as 64 words of 64 bits
+type BitmapBlock = [u64; 64];
+
+const BLOCK_BITS: usize = BLOCK_SZ * 8;
+
+/// A bitmap spanning `blocks` blocks from `start_block_id`
+pub struct Bitmap {
+    start_block_id: usize,
+    blocks: usize,
+}
+
+/// Split a bit number into a block, a word in the block, and a bit in the
+/// word
+fn decomposition(mut bit: usize) -> (usize, usize, usize) {
+    let block_pos = bit / BLOCK_BITS;
+    bit %= BLOCK_BITS;
+    (block_pos, bit / 64, bit % 64)
+}
+
+impl Bitmap {
+    pub fn new(start_block_id: usize, blocks: usize) -> Self {
+        Self {
+            start_block_id,
+            blocks,
+        }
+    }
+    /// Set the first clear bit, returning its number
+    pub fn alloc(&self, block_device: &Arc<dyn BlockDevice>) -> Option<usize> {
+        for block_id in 0..self.blocks {
+            let pos = get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .modify(0, |bitmap_block: &mut BitmapBlock| {
+                    let (bits64_pos, bits64) = bitmap_block
+                        .iter_mut()
+                        .enumerate()
+                        .find(|(_, bits64)| **bits64 != u64::MAX)?;
+                    let inner_pos = bits64.trailing_ones() as usize;
+                    *bits64 |= 1u64 << inner_pos;
+                    Some(block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos)
+                });
+            if pos.is_some() {
+                return pos;
+            }
+        }
+        None
+    }
+    /// Clear bit `bit`, which must be set
+    pub fn dealloc(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .modify(0, |bitmap_block: &mut BitmapBlock| {
+                assert!(bitmap_block[bits64_pos] & (1u64 << inner_pos) != 0);
+                bitmap_block[bits64_pos] &= !(1u64 << inner_pos);
+            });
+    }
+    /// Number of bits
+    pub fn maximum(&self) -> usize {
+        self.blocks * BLOCK_BITS
+    }
+}
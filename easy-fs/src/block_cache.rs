@@ -0,0 +1,162 @@
+//! Cache of disk blocks
+//!
+//! Every access to the disk goes through a [`BlockCache`], which holds a copy
+//! of a block in memory and writes it back when it is dropped or synced. At
+//! most [`BLOCK_CACHE_SIZE`] blocks are cached, and a block that nobody uses
+//! is written back and evicted to make room for another.
+
+use super::{BlockDevice, BLOCK_SZ};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use lazy_static::*;
+use spin::Mutex;
+
+/// A block in memory
+pub struct BlockCache {
+    cache: [u8; BLOCK_SZ],
+    block_id: usize,
+    block_device: Arc<dyn BlockDevice>,
+    /// whether `cache` has changed since it was read
+    modified: bool,
+}
+
+impl BlockCache {
+    /// Load block `block_id` from the disk
+    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
+        let mut cache = [0u8; BLOCK_SZ];
+        block_device.read_block(block_id, &mut cache);
+        Self {
+            cache,
+            block_id,
+            block_device,
+            modified: false,
+        }
+    }
+    fn addr_of_offset(&self, offset: usize) -> usize {
+        &self.cache[offset] as *const _ as usize
+    }
+    /// Get a reference to the `T` at `offset` in the block
+    pub fn get_ref<T>(&self, offset: usize) -> &T
+    where
+        T: Sized,
+    {
+        let type_size = core::mem::size_of::<T>();
+        assert!(offset + type_size <= BLOCK_SZ);
+        let addr = self.addr_of_offset(offset);
+        unsafe { &*(addr as *const T) }
+    }
+    /// Get a mutable reference to the `T` at `offset` in the block, marking
+    /// the block as modified
+    pub fn get_mut<T>(&mut self, offset: usize) -> &mut T
+    where
+        T: Sized,
+    {
+        let type_size = core::mem::size_of::<T>();
+        assert!(offset + type_size <= BLOCK_SZ);
+        self.modified = true;
+        let addr = self.addr_of_offset(offset);
+        unsafe { &mut *(addr as *mut T) }
+    }
+    /// Run `f` on the `T` at `offset`
+    pub fn read<T, V>(&self, offset: usize, f: impl FnOnce(&T) -> V) -> V {
+        f(self.get_ref(offset))
+    }
+    /// Run `f` on the `T` at `offset`, which it may change
+    pub fn modify<T, V>(&mut self, offset: usize, f: impl FnOnce(&mut T) -> V) -> V {
+        f(self.get_mut(offset))
+    }
+    /// Write the block back to the disk if it has changed
+    pub fn sync(&mut self) {
+        if self.modified {
+            self.modified = false;
+            self.block_device.write_block(self.block_id, &self.cache);
+        }
+    }
+}
+
+impl Drop for BlockCache {
+    fn drop(&mut self) {
+        self.sync()
+    }
+}
+
+/// number of blocks cached at most
+const BLOCK_CACHE_SIZE: usize = 16;
+
+/// The cached blocks, oldest first
+///
+/// Blocks are told apart by their device too, see [`device_id`], as there
+/// may be more than one filesystem at a time, like in tests.
+pub struct BlockCacheManager {
+    queue: VecDeque<(usize, usize, Arc<Mutex<BlockCache>>)>,
+}
+
+/// An id of `block_device`, unique while any of its blocks are cached, as the
+/// cache keeps it alive
+fn device_id(block_device: &Arc<dyn BlockDevice>) -> usize {
+    // the address of the data only, vtables of a type may be duplicated
+    Arc::as_ptr(block_device) as *const () as usize
+}
+
+impl BlockCacheManager {
+    fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+    /// Get the cache of block `block_id`, loading it if needed
+    pub fn get_block_cache(
+        &mut self,
+        block_id: usize,
+        block_device: Arc<dyn BlockDevice>,
+    ) -> Arc<Mutex<BlockCache>> {
+        let device_id = device_id(&block_device);
+        if let Some((_, _, cache)) = self
+            .queue
+            .iter()
+            .find(|(id, device, _)| *id == block_id && *device == device_id)
+        {
+            return Arc::clone(cache);
+        }
+        if self.queue.len() == BLOCK_CACHE_SIZE {
+            // evict the oldest block nobody holds
+            let idx = self
+                .queue
+                .iter()
+                .position(|(_, _, cache)| Arc::strong_count(cache) == 1)
+                .expect("Run out of BlockCache!");
+            self.queue.remove(idx);
+        }
+        let block_cache = Arc::new(Mutex::new(BlockCache::new(
+            block_id,
+            Arc::clone(&block_device),
+        )));
+        self.queue
+            .push_back((block_id, device_id, Arc::clone(&block_cache)));
+        block_cache
+    }
+}
+
+lazy_static! {
+    /// the global block cache
+    pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> =
+        Mutex::new(BlockCacheManager::new());
+}
+
+/// Get the cache of block `block_id` of `block_device`
+pub fn get_block_cache(
+    block_id: usize,
+    block_device: Arc<dyn BlockDevice>,
+) -> Arc<Mutex<BlockCache>> {
+    BLOCK_CACHE_MANAGER
+        .lock()
+        .get_block_cache(block_id, block_device)
+}
+
+/// Write all modified blocks back to the disk
+pub fn block_cache_sync_all() {
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    for (_, _, cache) in manager.queue.iter() {
+        cache.lock().sync();
+    }
+}
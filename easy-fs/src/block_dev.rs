@@ -0,0 +1,11 @@
+//! The interface to the disk
+
+use core::any::Any;
+
+/// A storage device accessed in blocks of [`crate::BLOCK_SZ`] bytes
+pub trait BlockDevice: Send + Sync + Any {
+    /// Read block `block_id` into `buf`
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    /// Write `buf` to block `block_id`
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+}
@@ -0,0 +1,50 @@
+//! A simple filesystem
+//!
+//! The disk is split into blocks of [`BLOCK_SZ`] bytes, laid out as:
+//!
+//! - the super block, see [`layout::SuperBlock`]
+//! - a bitmap of allocated inodes, then the inodes themselves
+//! - a bitmap of allocated data blocks, then the data blocks themselves
+//!
+//! All blocks go through a small cache in memory, see [`block_cache`]. Files
+//! live in a single root directory, and are accessed through [`Inode`]s.
+//!
+//! The crate only needs `alloc`, so that both the kernel and host tools can
+//! use it.
+
+#![no_std]
+
+extern crate alloc;
+
+mod bitmap;
+mod block_cache;
+mod block_dev;
+mod efs;
+mod layout;
+mod vfs;
+
+/// size of a block, in bytes
+pub const BLOCK_SZ: usize = 512;
+
+/// Why an operation on the filesystem failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FsError {
+    /// there is a file of that name already
+    Exists,
+    /// the name is empty
+    InvalidName,
+    /// the name is longer than [`NAME_LENGTH_LIMIT`]
+    NameTooLong,
+    /// there are no free inodes or data blocks left
+    NoSpace,
+    /// the file would grow beyond [`MAX_FILE_SIZE`]
+    TooBig,
+}
+
+use bitmap::Bitmap;
+use block_cache::{block_cache_sync_all, get_block_cache};
+pub use block_dev::BlockDevice;
+pub use efs::EasyFileSystem;
+use layout::*;
+pub use layout::{MAX_FILE_SIZE, NAME_LENGTH_LIMIT};
+pub use vfs::Inode;
@@ -0,0 +1,362 @@
+//! Files and directories, as seen by users of the crate
+//!
+//! The directory tree is flat: every file lives in the root directory.
+
+use super::{
+    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
+    EasyFileSystem, FsError, DIRENT_SZ, MAX_FILE_SIZE, NAME_LENGTH_LIMIT,
+};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::{Mutex, MutexGuard};
+
+/// A handle to an inode on the disk
+pub struct Inode {
+    block_id: usize,
+    block_offset: usize,
+    fs: Arc<Mutex<EasyFileSystem>>,
+    block_device: Arc<dyn BlockDevice>,
+}
+
+impl Inode {
+    /// The inode at `block_offset` in block `block_id`
+    pub fn new(
+        block_id: u32,
+        block_offset: usize,
+        fs: Arc<Mutex<EasyFileSystem>>,
+        block_device: Arc<dyn BlockDevice>,
+    ) -> Self {
+        Self {
+            block_id: block_id as usize,
+            block_offset,
+            fs,
+            block_device,
+        }
+    }
+    fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
+        get_block_cache(self.block_id, Arc::clone(&self.block_device))
+            .lock()
+            .read(self.block_offset, f)
+    }
+    fn modify_disk_inode<V>(&self, f: impl FnOnce(&mut DiskInode) -> V) -> V {
+        get_block_cache(self.block_id, Arc::clone(&self.block_device))
+            .lock()
+            .modify(self.block_offset, f)
+    }
+    /// Look `name` up in the directory `disk_inode`, returning its inode
+    /// number
+    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
+        // assert it is a directory
+        assert!(disk_inode.is_dir());
+        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        for i in 0..file_count {
+            assert_eq!(
+                disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
+                DIRENT_SZ,
+            );
+            if dirent.name() == name {
+                return Some(dirent.inode_number() as u32);
+            }
+        }
+        None
+    }
+    /// Look `name` up in this directory
+    pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
+        let fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            self.find_inode_id(name, disk_inode).map(|inode_id| {
+                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+                Arc::new(Self::new(
+                    block_id,
+                    block_offset,
+                    self.fs.clone(),
+                    self.block_device.clone(),
+                ))
+            })
+        })
+    }
+    /// Grow `disk_inode` to `new_size` bytes, allocating blocks as needed
+    ///
+    /// Nothing is allocated if there are not enough free blocks.
+    fn increase_size(
+        &self,
+        new_size: usize,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> Result<(), FsError> {
+        if new_size > MAX_FILE_SIZE {
+            return Err(FsError::TooBig);
+        }
+        let new_size = new_size as u32;
+        if new_size < disk_inode.size {
+            return Ok(());
+        }
+        let blocks_needed = disk_inode.blocks_num_needed(new_size);
+        let mut v: Vec<u32> = Vec::new();
+        for _ in 0..blocks_needed {
+            match fs.alloc_data() {
+                Some(block_id) => v.push(block_id),
+                None => {
+                    for block_id in v {
+                        fs.dealloc_data(block_id);
+                    }
+                    return Err(FsError::NoSpace);
+                }
+            }
+        }
+        disk_inode.increase_size(new_size, v, &self.block_device);
+        Ok(())
+    }
+    /// Create an empty file named `name` in this directory
+    pub fn create(&self, name: &str) -> Result<Arc<Inode>, FsError> {
+        if name.is_empty() {
+            return Err(FsError::InvalidName);
+        }
+        if name.len() > NAME_LENGTH_LIMIT {
+            return Err(FsError::NameTooLong);
+        }
+        let mut fs = self.fs.lock();
+        if self
+            .read_disk_inode(|root_inode| self.find_inode_id(name, root_inode))
+            .is_some()
+        {
+            return Err(FsError::Exists);
+        }
+        // create a new file
+        // alloc a inode with an indirect block
+        let new_inode_id = fs.alloc_inode().ok_or(FsError::NoSpace)?;
+        // initialize inode
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(DiskInodeType::File);
+            });
+        let appended = self.modify_disk_inode(|root_inode| {
+            // append file in the dirent
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            // increase size
+            self.increase_size(new_size, root_inode, &mut fs)?;
+            // write dirent
+            let dirent = DirEntry::new(name, new_inode_id);
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+            Ok(())
+        });
+        if let Err(err) = appended {
+            fs.dealloc_inode(new_inode_id);
+            return Err(err);
+        }
+
+        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        block_cache_sync_all();
+        // return inode
+        Ok(Arc::new(Self::new(
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        )))
+        // release efs lock automatically by compiler
+    }
+    /// Names of the entries in this directory
+    pub fn ls(&self) -> Vec<String> {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+            let mut v: Vec<String> = Vec::new();
+            for i in 0..file_count {
+                let mut dirent = DirEntry::empty();
+                assert_eq!(
+                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
+                    DIRENT_SZ,
+                );
+                v.push(String::from(dirent.name()));
+            }
+            v
+        })
+    }
+    /// Read from `offset` into `buf`, returning the number of bytes read
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+    }
+    /// Write `buf` at `offset`, growing the file as needed, and return the
+    /// number of bytes written
+    ///
+    /// Nothing is written if the file cannot grow large enough.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        let end = offset.checked_add(buf.len()).ok_or(FsError::TooBig)?;
+        let mut fs = self.fs.lock();
+        let size = self.modify_disk_inode(|disk_inode| {
+            self.increase_size(end, disk_inode, &mut fs)?;
+            Ok(disk_inode.write_at(offset, buf, &self.block_device))
+        });
+        block_cache_sync_all();
+        size
+    }
+    /// Size of the file in bytes
+    pub fn size(&self) -> usize {
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+    /// Truncate the file to zero bytes, freeing its blocks
+    pub fn clear(&self) {
+        let mut fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            let size = disk_inode.size;
+            let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
+            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
+            for data_block in data_blocks_dealloc.into_iter() {
+                fs.dealloc_data(data_block);
+            }
+        });
+        block_cache_sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BLOCK_SZ;
+    use alloc::vec;
+
+    /// A disk in memory
+    struct MemDisk(Mutex<Vec<[u8; BLOCK_SZ]>>);
+
+    impl BlockDevice for MemDisk {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0.lock()[block_id]);
+        }
+        fn write_block(&self, block_id: usize, buf: &[u8]) {
+            self.0.lock()[block_id].copy_from_slice(buf);
+        }
+    }
+
+    /// blocks of the test disks, enough for the 4096 inodes of one bitmap
+    /// block, and a few thousand data blocks
+    const TOTAL_BLOCKS: usize = 4096;
+
+    fn mem_disk() -> Arc<dyn BlockDevice> {
+        Arc::new(MemDisk(Mutex::new(vec![[0; BLOCK_SZ]; TOTAL_BLOCKS])))
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn write_read_clear() {
+        let efs = EasyFileSystem::create(mem_disk(), TOTAL_BLOCKS as u32, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        let file = root.create("file").unwrap();
+        assert_eq!(file.size(), 0);
+        // past the direct blocks and the first indirect block
+        let data = pattern(200 * BLOCK_SZ + 123);
+        assert_eq!(file.write_at(0, &data), Ok(data.len()));
+        assert_eq!(file.size(), data.len());
+
+        let file = root.find("file").unwrap();
+        let mut buf = vec![0; data.len() + 10];
+        assert_eq!(file.read_at(0, &mut buf), data.len());
+        assert_eq!(&buf[..data.len()], &data[..]);
+        let mut buf = [0; 100];
+        assert_eq!(file.read_at(1000, &mut buf), 100);
+        assert_eq!(&buf[..], &data[1000..1100]);
+        assert_eq!(root.ls(), vec![String::from("file")]);
+
+        file.clear();
+        assert_eq!(file.size(), 0);
+        assert_eq!(file.read_at(0, &mut buf), 0);
+        // freed blocks can be used again
+        assert_eq!(file.write_at(0, &data[..BLOCK_SZ]), Ok(BLOCK_SZ));
+        assert_eq!(file.read_at(0, &mut buf), 100);
+        assert_eq!(&buf[..], &data[..100]);
+    }
+
+    #[test]
+    fn reopen() {
+        let disk = mem_disk();
+        let efs = EasyFileSystem::create(Arc::clone(&disk), TOTAL_BLOCKS as u32, 1);
+        let data = pattern(3 * BLOCK_SZ);
+        let root = EasyFileSystem::root_inode(&efs);
+        root.create("a").unwrap().write_at(0, &data[..10]).unwrap();
+        root.create("b").unwrap().write_at(0, &data).unwrap();
+        drop(root);
+        drop(efs);
+
+        let efs = EasyFileSystem::open(disk).unwrap();
+        let root = EasyFileSystem::root_inode(&efs);
+        assert_eq!(root.ls(), vec![String::from("a"), String::from("b")]);
+        let file = root.find("b").unwrap();
+        let mut buf = vec![0; data.len()];
+        assert_eq!(file.read_at(0, &mut buf), data.len());
+        assert_eq!(buf, data);
+        assert!(root.find("c").is_none());
+    }
+
+    #[test]
+    fn bad_names() {
+        let efs = EasyFileSystem::create(mem_disk(), TOTAL_BLOCKS as u32, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        let longest = "x".repeat(NAME_LENGTH_LIMIT);
+        assert!(root.create(&longest).is_ok());
+        assert_eq!(root.create(&longest).err(), Some(FsError::Exists));
+        assert_eq!(
+            root.create(&"x".repeat(NAME_LENGTH_LIMIT + 1)).err(),
+            Some(FsError::NameTooLong)
+        );
+        assert_eq!(root.create("").err(), Some(FsError::InvalidName));
+        assert_eq!(root.ls(), vec![longest]);
+    }
+
+    #[test]
+    fn disk_full() {
+        let efs = EasyFileSystem::create(mem_disk(), TOTAL_BLOCKS as u32, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        let file = root.create("file").unwrap();
+        // more than the data area
+        let data = pattern(TOTAL_BLOCKS * BLOCK_SZ);
+        assert_eq!(file.write_at(0, &data), Err(FsError::NoSpace));
+        assert_eq!(file.size(), 0);
+        // the blocks allocated before running out were freed
+        let quarter = data.len() / 4;
+        assert_eq!(file.write_at(0, &data[..quarter]), Ok(quarter));
+        assert_eq!(file.write_at(quarter, &data[quarter..]), Err(FsError::NoSpace));
+        assert_eq!(file.size(), quarter);
+        file.clear();
+        assert_eq!(file.write_at(0, &data[..quarter]), Ok(quarter));
+    }
+
+    #[test]
+    fn file_too_big() {
+        let efs = EasyFileSystem::create(mem_disk(), TOTAL_BLOCKS as u32, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        let file = root.create("file").unwrap();
+        assert_eq!(file.write_at(MAX_FILE_SIZE, &[1]), Err(FsError::TooBig));
+        assert_eq!(file.write_at(usize::MAX, &[1]), Err(FsError::TooBig));
+        assert_eq!(file.size(), 0);
+    }
+
+    #[test]
+    fn out_of_inodes() {
+        let efs = EasyFileSystem::create(mem_disk(), TOTAL_BLOCKS as u32, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        root.create("file").unwrap();
+        // take all the inodes left
+        while efs.lock().alloc_inode().is_some() {}
+        assert_eq!(root.create("one more").err(), Some(FsError::NoSpace));
+        assert!(root.find("one more").is_none());
+        assert_eq!(root.ls(), vec![String::from("file")]);
+    }
+
+    #[test]
+    fn not_a_filesystem() {
+        assert!(EasyFileSystem::open(mem_disk()).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+//! Board information from the device tree
+//!
+//! The SBI passes the kernel a flattened device tree describing the machine.
+//! It is parsed once at boot, before the frame allocator gets to reuse the
+//! memory it sits in. Anything the tree does not tell falls back to the QEMU
+//! `virt` defaults in [`crate::config`].
+
+use crate::config::{
+    CLOCK_FREQ, MEMORY_END, PLIC_BASE, PLIC_SIZE, UART_BASE, UART_IRQ, UART_SIZE, VIRTIO_BASE,
+    VIRTIO_SIZE, VIRTIO_SLOTS,
+};
+use crate::fdt::{read_cells, Fdt, Token};
+use crate::sync::UPSafeCell;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+#[derive(Copy, Clone, Debug)]
+/// a range of device registers
+pub struct Mmio {
+    pub base: usize,
+    pub size: usize,
+}
+
+#[derive(Copy, Clone)]
+/// what the kernel needs to know about the machine
+pub struct BoardInfo {
+    /// end of the RAM the kernel is loaded in
+    pub memory_end: usize,
+    /// frequency of the `time` CSR, in Hz
+    pub clock_freq: usize,
+    pub uart: Mmio,
+    /// PLIC interrupt source of the UART
+    pub uart_irq: usize,
+    pub plic: Mmio,
+    /// virtio-mmio slots
+    pub virtio: [Option<Mmio>; VIRTIO_SLOTS],
+}
+
+impl BoardInfo {
+    /// Device registers to map in kernel space
+    pub fn mmio(&self) -> Vec<Mmio> {
+        let mut mmio = vec![self.plic, self.uart];
+        mmio.extend(self.virtio.iter().flatten());
+        mmio
+    }
+}
+
+lazy_static! {
+    static ref BOARD: UPSafeCell<BoardInfo> = unsafe {
+        let mut virtio = [None; VIRTIO_SLOTS];
+        for (i, slot) in virtio.iter_mut().enumerate() {
+            *slot = Some(Mmio {
+                base: VIRTIO_BASE + i * VIRTIO_SIZE,
+                size: VIRTIO_SIZE,
+            });
+        }
+        UPSafeCell::new(BoardInfo {
+            memory_end: MEMORY_END,
+            clock_freq: CLOCK_FREQ,
+            uart: Mmio {
+                base: UART_BASE,
+                size: UART_SIZE,
+            },
+            uart_irq: UART_IRQ,
+            plic: Mmio {
+                base: PLIC_BASE,
+                size: PLIC_SIZE,
+            },
+            virtio,
+        })
+    };
+}
+
+/// Get the board information
+pub fn board() -> BoardInfo {
+    *BOARD.exclusive_access()
+}
+
+/// what we have seen of a node so far
+struct Node<'a> {
+    /// `#address-cells` and `#size-cells`, for the `reg` of children
+    address_cells: usize,
+    size_cells: usize,
+    device_type: &'a [u8],
+    compatible: &'a [u8],
+    reg: &'a [u8],
+    interrupts: &'a [u8],
+    timebase_frequency: &'a [u8],
+}
+
+impl<'a> Node<'a> {
+    fn new() -> Self {
+        Self {
+            address_cells: 2,
+            size_cells: 1,
+            device_type: &[],
+            compatible: &[],
+            reg: &[],
+            interrupts: &[],
+            timebase_frequency: &[],
+        }
+    }
+    /// whether one of the nul-separated strings in `compatible` is `name`
+    fn is_compatible(&self, name: &str) -> bool {
+        self.compatible
+            .split(|&c| c == 0)
+            .any(|s| s == name.as_bytes())
+    }
+    /// the first range in `reg`, given the cells of the parent
+    fn first_reg(&self, address_cells: usize, size_cells: usize) -> Option<Mmio> {
+        let (base, rest) = read_cells(self.reg, address_cells)?;
+        let (size, _) = read_cells(rest, size_cells)?;
+        Some(Mmio { base, size })
+    }
+}
+
+/// Parse the device tree at `dtb`, and remember what it says about the board
+pub fn init(dtb: usize) {
+    extern "C" {
+        fn skernel();
+    }
+    let fdt = match unsafe { Fdt::from_addr(dtb) } {
+        Ok(fdt) => fdt,
+        Err(err) => {
+            warn!(
+                "[kernel] No device tree at {:#x} ({}), assuming QEMU virt",
+                dtb, err
+            );
+            return;
+        }
+    };
+    let mut info = board();
+    let (mut found_uart, mut found_plic) = (false, false);
+    let mut virtio_found = 0;
+    let mut stack: Vec<Node> = Vec::new();
+    for token in fdt.tokens() {
+        match token {
+            Token::BeginNode(_) => stack.push(Node::new()),
+            Token::Prop(name, value) => {
+                let node = match stack.last_mut() {
+                    Some(node) => node,
+                    None => break,
+                };
+                match name {
+                    "#address-cells" => {
+                        node.address_cells = read_cells(value, 1).map_or(2, |(n, _)| n)
+                    }
+                    "#size-cells" => node.size_cells = read_cells(value, 1).map_or(1, |(n, _)| n),
+                    "device_type" => node.device_type = value,
+                    "compatible" => node.compatible = value,
+                    "reg" => node.reg = value,
+                    "interrupts" => node.interrupts = value,
+                    "timebase-frequency" => node.timebase_frequency = value,
+                    _ => {}
+                }
+            }
+            Token::EndNode => {
+                let node = match stack.pop() {
+                    Some(node) => node,
+                    None => break,
+                };
+                let (address_cells, size_cells) = stack
+                    .last()
+                    .map_or((2, 1), |parent| (parent.address_cells, parent.size_cells));
+                let reg = node.first_reg(address_cells, size_cells);
+                if node.device_type == b"memory\0" {
+                    // the bank the kernel is loaded in
+                    if let Some(Mmio { base, size }) = reg {
+                        match base.checked_add(size) {
+                            Some(end) if (base..end).contains(&(skernel as usize)) => {
+                                info.memory_end = end;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                if let Some((freq, _)) =
+                    read_cells(node.timebase_frequency, node.timebase_frequency.len() / 4)
+                {
+                    if freq != 0 {
+                        info.clock_freq = freq;
+                    }
+                }
+                if !found_uart && node.is_compatible("ns16550a") {
+                    if let (Some(uart), Some((irq, _))) = (reg, read_cells(node.interrupts, 1)) {
+                        info.uart = uart;
+                        info.uart_irq = irq;
+                        found_uart = true;
+                    }
+                }
+                if !found_plic
+                    && (node.is_compatible("riscv,plic0")
+                        || node.is_compatible("sifive,plic-1.0.0"))
+                {
+                    if let Some(plic) = reg {
+                        info.plic = plic;
+                        found_plic = true;
+                    }
+                }
+                if virtio_found < VIRTIO_SLOTS && node.is_compatible("virtio,mmio") {
+                    if let Some(slot) = reg {
+                        if virtio_found == 0 {
+                            info.virtio = [None; VIRTIO_SLOTS];
+                        }
+                        info.virtio[virtio_found] = Some(slot);
+                        virtio_found += 1;
+                    }
+                }
+            }
+        }
+    }
+    info!(
+        "[kernel] memory ends at {:#x}, timebase {} Hz, uart {:#x} (irq {}), plic {:#x}",
+        info.memory_end, info.clock_freq, info.uart.base, info.uart_irq, info.plic.base
+    );
+    *BOARD.exclusive_access() = info;
+}
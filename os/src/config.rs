@@ -2,9 +2,55 @@
 
 pub const USER_STACK_SIZE: usize = 4096;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
-pub const KERNEL_HEAP_SIZE: usize = 0x20000;
-pub const MAX_APP_NUM: usize = 16;
-pub const APP_BASE_ADDRESS: usize = 0x80400000;
-pub const APP_SIZE_LIMIT: usize = 0x20000;
+pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+pub const PAGE_SIZE: usize = 0x1000;
+pub const PAGE_SIZE_BITS: usize = 0xc;
+/// Maximum total size of the loadable segments of an app
+pub const APP_SIZE_LIMIT: usize = 0x10_0000;
+/// Largest heap a task may grow with `sys_sbrk`
+pub const USER_HEAP_LIMIT: usize = 0x40_0000;
+pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
+pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+// QEMU `virt` with 128 MiB of RAM, for whatever the device tree does not tell
+pub const MEMORY_END: usize = 0x88000000;
 pub const CLOCK_FREQ: usize = 12500000;
+pub const PLIC_BASE: usize = 0x0c00_0000;
+pub const PLIC_SIZE: usize = 0x40_0000;
+pub const UART_BASE: usize = 0x1000_0000;
+pub const UART_SIZE: usize = 0x1000;
+/// PLIC interrupt source of the UART
+pub const UART_IRQ: usize = 10;
+pub const VIRTIO_BASE: usize = 0x1000_1000;
+pub const VIRTIO_SIZE: usize = 0x1000;
+/// number of virtio-mmio slots
+pub const VIRTIO_SLOTS: usize = 8;
 pub const MAX_SYSCALL_NUM: usize = 500;
+/// file descriptors must be below this, so that fd tables cannot grow
+/// without bounds
+pub const MAX_FD: usize = 1024;
+/// priority of tasks loaded from scratch, like the init process; forked tasks
+/// inherit the priority of their parent
+pub const DEFAULT_PRIORITY: usize = 16;
+/// the lowest priority accepted by `sys_set_priority`
+pub const MIN_PRIORITY: usize = 2;
+/// MLFQ: time slice of each level in timer ticks, from the highest level down
+#[cfg(feature = "sched-mlfq")]
+pub const MLFQ_QUANTA: [usize; 4] = [1, 2, 4, 8];
+/// MLFQ: how often all tasks are moved back to the highest level, in ticks
+#[cfg(feature = "sched-mlfq")]
+pub const MLFQ_BOOST_INTERVAL: usize = 100;
+/// Name of the app started as the init process
+pub const INITPROC_NAME: &str = "user_shell";
+/// how many of the latest exits are listed when the kernel shuts down; older
+/// ones are only counted
+pub const MAX_EXIT_RECORDS: usize = 64;
+
+/// Return (bottom, top) of the kernel stack of process `pid` in kernel space.
+///
+/// Kernel stacks sit right below the trampoline, each followed by a guard
+/// page.
+pub fn kernel_stack_position(pid: usize) -> (usize, usize) {
+    let top = TRAMPOLINE - pid * (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let bottom = top - KERNEL_STACK_SIZE;
+    (bottom, top)
+}
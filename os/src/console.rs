@@ -1,15 +1,72 @@
-//! SBI console driver, for text output
+//! Console, for text input and output
+//!
+//! Early on, the console goes through SBI calls one character at a time.
+//! Once devices are up, [`switch_to_uart`] moves it to the UART driver, which
+//! buffers output and receives input by interrupts.
+//!
+//! Input goes through a small line discipline: characters are echoed as they
+//! are typed, backspace edits the current line, and a line is only handed out
+//! to readers once it is complete. Readers sleep until the UART receives more,
+//! see [`wait_for_input`].
 
-use crate::sbi::console_putchar;
+use crate::drivers::UART;
+use crate::sbi::{console_getchar, console_putchar};
+use crate::sync::UPSafeCell;
+use crate::task::suspend_current_and_run_next;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+/// whether the console goes through the UART driver rather than SBI
+static USE_UART: AtomicBool = AtomicBool::new(false);
+
+/// Send console I/O through the UART driver, which must be initialized
+pub fn switch_to_uart() {
+    USE_UART.store(true, Ordering::Relaxed);
+}
+
+/// Go back to SBI calls, which work whatever state the kernel is in
+pub fn switch_to_sbi() {
+    USE_UART.store(false, Ordering::Relaxed);
+}
+
+/// Wait until all console output has been sent
+pub fn flush() {
+    if USE_UART.load(Ordering::Relaxed) {
+        UART.flush();
+    }
+}
+
+/// Write `bytes` to the console as they are
+pub fn putbytes(bytes: &[u8]) {
+    if USE_UART.load(Ordering::Relaxed) {
+        UART.write(bytes);
+    } else {
+        for &c in bytes {
+            console_putchar(c as usize);
+        }
+    }
+}
+
+fn getchar() -> Option<u8> {
+    if USE_UART.load(Ordering::Relaxed) {
+        UART.read()
+    } else {
+        // the legacy SBI call returns -1 when there is no input
+        match console_getchar() {
+            usize::MAX => None,
+            c => Some(c as u8),
+        }
+    }
+}
 
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
-        }
+        putbytes(s.as_bytes());
         Ok(())
     }
 }
@@ -33,3 +90,99 @@ macro_rules! println {
         $crate::console::print(format_args!(concat!($fmt, "\n") $(, $($arg)+)?));
     }
 }
+
+const BS: u8 = 0x08;
+const DEL: u8 = 0x7f;
+/// Ctrl-D, which ends the input
+const EOT: u8 = 0x04;
+
+/// console input, one line at a time
+struct LineBuffer {
+    /// the line being typed
+    line: Vec<u8>,
+    /// complete lines, not read yet
+    ready: VecDeque<u8>,
+    /// a Ctrl-D on an empty line is waiting to be read as end of file
+    eof: bool,
+}
+
+impl LineBuffer {
+    fn push(&mut self, c: u8) {
+        match c {
+            b'\r' | b'\n' => {
+                putbytes(b"\n");
+                self.line.push(b'\n');
+                self.ready.extend(self.line.drain(..));
+            }
+            BS | DEL => {
+                if self.line.pop().is_some() {
+                    putbytes(b"\x08 \x08");
+                }
+            }
+            EOT => {
+                if self.line.is_empty() {
+                    self.eof = true;
+                } else {
+                    self.ready.extend(self.line.drain(..));
+                }
+            }
+            _ => {
+                putbytes(&[c]);
+                self.line.push(c);
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref STDIN: UPSafeCell<LineBuffer> = unsafe {
+        UPSafeCell::new(LineBuffer {
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            eof: false,
+        })
+    };
+}
+
+/// Take at most `max` bytes of complete input lines, without blocking.
+///
+/// Reads stop at the end of a line. Returns `None` if no line is complete
+/// yet, and an empty `Vec` at the end of file.
+pub fn read_input(max: usize) -> Option<Vec<u8>> {
+    let mut stdin = STDIN.exclusive_access();
+    while let Some(c) = getchar() {
+        stdin.push(c);
+    }
+    if stdin.ready.is_empty() {
+        if stdin.eof {
+            stdin.eof = false;
+            return Some(Vec::new());
+        }
+        return None;
+    }
+    let mut data = Vec::new();
+    while data.len() < max {
+        match stdin.ready.pop_front() {
+            Some(c) => {
+                data.push(c);
+                if c == b'\n' {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+    Some(data)
+}
+
+/// Let other tasks run until more input may have come in, after
+/// [`read_input`] found none
+///
+/// SBI calls have no input interrupt, so the task is only suspended then.
+pub fn wait_for_input() {
+    if USE_UART.load(Ordering::Relaxed) {
+        UART.wait_for_input();
+    } else {
+        suspend_current_and_run_next();
+    }
+}
@@ -0,0 +1,154 @@
+//! Block devices
+//!
+//! A [`BlockDevice`] reads and writes whole blocks of [`BLOCK_SIZE`] bytes.
+//! The trait comes from `easy-fs`, which keeps its filesystem on one. The only
+//! device we have is a virtio-blk one.
+//!
+//! The trait has no way to report errors, so failed requests are logged, and
+//! a failed read leaves zeros in the buffer.
+
+use super::virtio::{self, Buffer, Registers, VirtQueue, DEVICE_ID_BLOCK};
+use crate::sync::UPSafeCell;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::mem::size_of;
+pub use easy_fs::BlockDevice;
+
+pub const BLOCK_SIZE: usize = easy_fs::BLOCK_SZ;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+/// a request, with room for the data and the status the device writes back
+///
+/// Callers' buffers may be on a kernel stack, which is not identity mapped,
+/// so data goes through here.
+#[repr(C)]
+struct Request {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+    data: [u8; BLOCK_SIZE],
+    status: u8,
+}
+
+const HEADER_SIZE: usize = 16;
+
+struct VirtIOBlockInner {
+    regs: Registers,
+    queue: VirtQueue,
+    request: Box<Request>,
+}
+
+impl VirtIOBlockInner {
+    /// Carry out a request on `block_id`, which fails if the block is out of
+    /// range or the device reports an error
+    fn submit(&mut self, kind: u32, block_id: usize) -> Result<(), &'static str> {
+        if block_id >= self.capacity() {
+            return Err("out of range");
+        }
+        let request = &mut *self.request;
+        request.kind = kind;
+        request.sector = block_id as u64;
+        request.status = 0xff;
+        let base = request as *mut Request as usize;
+        let data = if kind == VIRTIO_BLK_T_IN {
+            Buffer::Writable(base + HEADER_SIZE, BLOCK_SIZE)
+        } else {
+            Buffer::Readable(base + HEADER_SIZE, BLOCK_SIZE)
+        };
+        let status = &request.status as *const u8 as usize;
+        self.queue.submit(
+            &self.regs,
+            &[
+                Buffer::Readable(base, HEADER_SIZE),
+                data,
+                Buffer::Writable(status, size_of::<u8>()),
+            ],
+        );
+        if self.request.status != VIRTIO_BLK_S_OK {
+            return Err("I/O error");
+        }
+        Ok(())
+    }
+    /// capacity in blocks, from the device configuration
+    fn capacity(&self) -> usize {
+        self.regs.config::<u64>(0) as usize
+    }
+}
+
+/// A virtio-blk device
+pub struct VirtIOBlock {
+    inner: UPSafeCell<VirtIOBlockInner>,
+}
+
+impl VirtIOBlock {
+    /// # Safety
+    ///
+    /// `base` must be the address of a virtio-mmio slot with a block device.
+    pub unsafe fn new(base: usize) -> Result<Self, &'static str> {
+        let (regs, queue) = virtio::init(base)?;
+        Ok(Self {
+            inner: UPSafeCell::new(VirtIOBlockInner {
+                regs,
+                queue,
+                request: Box::new(Request {
+                    kind: 0,
+                    reserved: 0,
+                    sector: 0,
+                    data: [0; BLOCK_SIZE],
+                    status: 0,
+                }),
+            }),
+        })
+    }
+    /// capacity in blocks
+    pub fn capacity(&self) -> usize {
+        self.inner.exclusive_access().capacity()
+    }
+}
+
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let mut inner = self.inner.exclusive_access();
+        match inner.submit(VIRTIO_BLK_T_IN, block_id) {
+            Ok(()) => buf.copy_from_slice(&inner.request.data),
+            Err(err) => {
+                error!("[kernel] Failed to read block {}: {}", block_id, err);
+                buf.fill(0);
+            }
+        }
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut inner = self.inner.exclusive_access();
+        inner.request.data.copy_from_slice(buf);
+        if let Err(err) = inner.submit(VIRTIO_BLK_T_OUT, block_id) {
+            error!("[kernel] Failed to write block {}: {}", block_id, err);
+        }
+    }
+}
+
+/// Find a virtio-blk device among the virtio-mmio slots at `bases`
+pub fn probe(bases: impl Iterator<Item = usize>) -> Option<Arc<dyn BlockDevice>> {
+    for base in bases {
+        if unsafe { virtio::probe(base) } != Some(DEVICE_ID_BLOCK) {
+            continue;
+        }
+        match unsafe { VirtIOBlock::new(base) } {
+            Ok(device) => {
+                info!(
+                    "[kernel] virtio-blk at {:#x}, {} blocks",
+                    base,
+                    device.capacity()
+                );
+                return Some(Arc::new(device));
+            }
+            Err(err) => warn!(
+                "[kernel] Failed to set up virtio-blk at {:#x}: {}",
+                base, err
+            ),
+        }
+    }
+    None
+}
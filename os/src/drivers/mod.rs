@@ -0,0 +1,74 @@
+//! Device drivers
+//!
+//! Devices are found at the addresses given by the device tree, see
+//! [`crate::board`], and interrupt the supervisor through the [`Plic`].
+
+mod block;
+mod plic;
+mod uart;
+mod virtio;
+
+pub use block::{BlockDevice, VirtIOBlock, BLOCK_SIZE};
+pub use plic::{handle_external_interrupt, register_irq_handler, Plic, PLIC};
+pub use uart::Uart;
+
+use crate::board::board;
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use lazy_static::*;
+use riscv::register::sie;
+
+lazy_static! {
+    /// the serial console
+    pub static ref UART: Uart = unsafe { Uart::new(board().uart.base) };
+    /// the disk, if there is one
+    static ref BLOCK_DEVICE: UPSafeCell<Option<Arc<dyn BlockDevice>>> =
+        unsafe { UPSafeCell::new(None) };
+}
+
+/// Get the disk, if there is one
+pub fn block_device() -> Option<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICE.exclusive_access().clone()
+}
+
+/// Initialize devices, and let their interrupts through
+pub fn init() {
+    plic::init();
+    UART.init();
+    register_irq_handler(board().uart_irq, 1, || UART.handle_irq());
+    unsafe {
+        sie::set_sext();
+    }
+    let slots = board().virtio;
+    *BLOCK_DEVICE.exclusive_access() = block::probe(slots.iter().flatten().map(|slot| slot.base));
+}
+
+/// Check that blocks read back the same after being written, leaving the
+/// disk as it was
+///
+/// The filesystem is overwritten meanwhile, so this is only for a scratch
+/// disk, see `BLOCK_TEST` in the Makefile.
+#[cfg(feature = "block-test")]
+pub fn block_device_test() {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    let device = match block_device() {
+        Some(device) => device,
+        None => {
+            warn!("[kernel] block_device_test skipped, no disk");
+            return;
+        }
+    };
+    let mut saved = vec![0u8; BLOCK_SIZE];
+    let mut buf = vec![0u8; BLOCK_SIZE];
+    for block_id in 0..4 {
+        device.read_block(block_id, &mut saved);
+        let pattern: Vec<u8> = (0..BLOCK_SIZE).map(|i| (i + block_id) as u8).collect();
+        device.write_block(block_id, &pattern);
+        device.read_block(block_id, &mut buf);
+        assert_eq!(buf, pattern);
+        device.write_block(block_id, &saved);
+    }
+    info!("[kernel] block_device_test passed!");
+}
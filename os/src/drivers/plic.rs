@@ -0,0 +1,116 @@
+//! Platform-Level Interrupt Controller
+//!
+//! Only the S-mode context of hart 0 is used, which is context 1 on QEMU
+//! `virt`.
+//!
+//! Drivers hook their interrupt sources with [`register_irq_handler`], and
+//! [`handle_external_interrupt`] calls the right handler for each interrupt
+//! the PLIC hands out.
+
+use crate::board::board;
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use core::ptr::{read_volatile, write_volatile};
+use lazy_static::*;
+
+const PRIORITY: usize = 0x0;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+/// S-mode of hart 0
+const SUPERVISOR_CONTEXT: usize = 1;
+
+/// a PLIC at `base`
+pub struct Plic {
+    base: usize,
+}
+
+impl Plic {
+    pub fn new(base: usize) -> Self {
+        Self { base }
+    }
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base + offset) as *mut u32
+    }
+    /// Set the priority of source `irq`, 0 meaning never interrupt
+    pub fn set_priority(&self, irq: usize, priority: u32) {
+        unsafe { write_volatile(self.reg(PRIORITY + irq * 4), priority) };
+    }
+    /// Let source `irq` interrupt the supervisor
+    pub fn enable(&self, irq: usize) {
+        let reg = self.enable_reg(irq);
+        unsafe { write_volatile(reg, read_volatile(reg) | 1 << (irq % 32)) };
+    }
+    /// Stop source `irq` from interrupting the supervisor
+    pub fn disable(&self, irq: usize) {
+        let reg = self.enable_reg(irq);
+        unsafe { write_volatile(reg, read_volatile(reg) & !(1 << (irq % 32))) };
+    }
+    fn enable_reg(&self, irq: usize) -> *mut u32 {
+        self.reg(ENABLE + SUPERVISOR_CONTEXT * ENABLE_STRIDE + irq / 32 * 4)
+    }
+    /// Only interrupt the supervisor for priorities above `threshold`
+    pub fn set_threshold(&self, threshold: u32) {
+        let reg = self.reg(CONTEXT + SUPERVISOR_CONTEXT * CONTEXT_STRIDE + THRESHOLD);
+        unsafe { write_volatile(reg, threshold) };
+    }
+    /// Take the pending interrupt with the highest priority, if any
+    pub fn claim(&self) -> Option<usize> {
+        let reg = self.reg(CONTEXT + SUPERVISOR_CONTEXT * CONTEXT_STRIDE + CLAIM);
+        match unsafe { read_volatile(reg) } {
+            0 => None,
+            irq => Some(irq as usize),
+        }
+    }
+    /// Tell that the interrupt of source `irq` has been handled
+    pub fn complete(&self, irq: usize) {
+        let reg = self.reg(CONTEXT + SUPERVISOR_CONTEXT * CONTEXT_STRIDE + CLAIM);
+        unsafe { write_volatile(reg, irq as u32) };
+    }
+}
+
+lazy_static! {
+    /// the interrupt controller
+    pub static ref PLIC: Plic = Plic::new(board().plic.base);
+    /// handlers of interrupt sources, by source
+    static ref IRQ_HANDLERS: UPSafeCell<BTreeMap<usize, fn()>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Let all interrupts with a priority through to the supervisor
+pub fn init() {
+    PLIC.set_threshold(0);
+}
+
+/// Call `handler` for each interrupt from source `irq`, which is enabled with
+/// `priority`
+pub fn register_irq_handler(irq: usize, priority: u32, handler: fn()) {
+    IRQ_HANDLERS.exclusive_access().insert(irq, handler);
+    PLIC.set_priority(irq, priority);
+    PLIC.enable(irq);
+}
+
+/// Handle a supervisor external interrupt
+///
+/// Sources nobody handles are disabled, so that they do not keep coming.
+pub fn handle_external_interrupt() {
+    while let Some(irq) = PLIC.claim() {
+        // do not hold the registry while the handler runs
+        let handler = IRQ_HANDLERS.exclusive_access().get(&irq).copied();
+        match handler {
+            Some(handler) => handler(),
+            None => {
+                warn!(
+                    "[kernel] Unexpected interrupt from source {}, disabling it",
+                    irq
+                );
+                PLIC.disable(irq);
+            }
+        }
+        PLIC.complete(irq);
+    }
+}
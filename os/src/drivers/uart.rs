@@ -0,0 +1,203 @@
+//! NS16550A UART driver
+//!
+//! Output is queued in a ring buffer and fed to the transmitter whenever its
+//! holding register is empty, with an interrupt asking for more. Input is
+//! moved into another ring buffer by the receive interrupt, which also wakes
+//! up tasks waiting for it.
+
+use crate::sync::{UPSafeCell, WaitQueue};
+use core::ptr::{read_volatile, write_volatile};
+
+// register offsets
+const RBR: usize = 0; // receive buffer, read
+const THR: usize = 0; // transmit holding, write
+const DLL: usize = 0; // divisor latch low, with DLAB
+const IER: usize = 1; // interrupt enable
+const DLM: usize = 1; // divisor latch high, with DLAB
+const FCR: usize = 2; // FIFO control, write
+const LCR: usize = 3; // line control
+const MCR: usize = 4; // modem control
+const LSR: usize = 5; // line status
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_TX_EMPTY: u8 = 1 << 1;
+const FCR_ENABLE_AND_CLEAR: u8 = 0b111;
+const LCR_8N1: u8 = 0b11;
+const LCR_DLAB: u8 = 1 << 7;
+/// DTR, RTS, and OUT2 which gates the interrupt line
+const MCR_DTR_RTS_OUT2: u8 = 0b1011;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+const BUFFER_SIZE: usize = 1024;
+
+/// A fixed-size FIFO of bytes
+struct RingBuffer {
+    buf: [u8; BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    fn is_full(&self) -> bool {
+        self.len == BUFFER_SIZE
+    }
+    /// Append `c`, returning false if there is no room
+    fn push(&mut self, c: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.buf[(self.head + self.len) % BUFFER_SIZE] = c;
+        self.len += 1;
+        true
+    }
+    fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        let c = self.buf[self.head];
+        self.head = (self.head + 1) % BUFFER_SIZE;
+        self.len -= 1;
+        Some(c)
+    }
+}
+
+/// registers of a UART at `base`
+struct Registers {
+    base: usize,
+}
+
+impl Registers {
+    fn read(&self, reg: usize) -> u8 {
+        unsafe { read_volatile((self.base + reg) as *const u8) }
+    }
+    fn write(&self, reg: usize, value: u8) {
+        unsafe { write_volatile((self.base + reg) as *mut u8, value) };
+    }
+}
+
+struct UartInner {
+    regs: Registers,
+    tx: RingBuffer,
+    rx: RingBuffer,
+}
+
+impl UartInner {
+    /// Feed queued output to the transmitter while it takes it, and ask for
+    /// an interrupt when it can take more
+    fn transmit(&mut self) {
+        while self.regs.read(LSR) & LSR_THR_EMPTY != 0 {
+            match self.tx.pop() {
+                Some(c) => self.regs.write(THR, c),
+                None => break,
+            }
+        }
+        let ier = if self.tx.is_empty() {
+            IER_RX_AVAILABLE
+        } else {
+            IER_RX_AVAILABLE | IER_TX_EMPTY
+        };
+        self.regs.write(IER, ier);
+    }
+    /// Move received characters into the input buffer, dropping them if it
+    /// is full, and return whether there were any
+    fn receive(&mut self) -> bool {
+        let mut received = false;
+        while self.regs.read(LSR) & LSR_DATA_READY != 0 {
+            let c = self.regs.read(RBR);
+            self.rx.push(c);
+            received = true;
+        }
+        received
+    }
+}
+
+/// An NS16550A UART
+pub struct Uart {
+    inner: UPSafeCell<UartInner>,
+    /// tasks waiting for input
+    readers: WaitQueue,
+}
+
+impl Uart {
+    /// # Safety
+    ///
+    /// `base` must be the address of the registers of an NS16550A.
+    pub unsafe fn new(base: usize) -> Self {
+        Self {
+            inner: UPSafeCell::new(UartInner {
+                regs: Registers { base },
+                tx: RingBuffer::new(),
+                rx: RingBuffer::new(),
+            }),
+            readers: WaitQueue::new(),
+        }
+    }
+    /// Set the line to 8N1 and enable FIFOs and the receive interrupt
+    pub fn init(&self) {
+        let inner = self.inner.exclusive_access();
+        let regs = &inner.regs;
+        regs.write(IER, 0);
+        // the divisor does not matter on QEMU, pick 38400 baud for 1.8432 MHz
+        regs.write(LCR, LCR_DLAB);
+        regs.write(DLL, 3);
+        regs.write(DLM, 0);
+        regs.write(LCR, LCR_8N1);
+        regs.write(FCR, FCR_ENABLE_AND_CLEAR);
+        regs.write(MCR, MCR_DTR_RTS_OUT2);
+        regs.write(IER, IER_RX_AVAILABLE);
+    }
+    /// Queue `bytes` for output
+    ///
+    /// Waits for the transmitter only when the output buffer is full.
+    pub fn write(&self, bytes: &[u8]) {
+        let mut inner = self.inner.exclusive_access();
+        for &c in bytes {
+            while !inner.tx.push(c) {
+                inner.transmit();
+            }
+        }
+        inner.transmit();
+    }
+    /// Wait until all queued output has been sent
+    pub fn flush(&self) {
+        let mut inner = self.inner.exclusive_access();
+        while !inner.tx.is_empty() {
+            inner.transmit();
+        }
+    }
+    /// Take a received character, if any
+    pub fn read(&self) -> Option<u8> {
+        let mut inner = self.inner.exclusive_access();
+        // pick up whatever came in while interrupts were off
+        inner.receive();
+        inner.rx.pop()
+    }
+    /// Block the current task until more input is received
+    ///
+    /// Callers must have found no input with [`Uart::read`] since interrupts
+    /// were last enabled, or the input may have come in already.
+    pub fn wait_for_input(&self) {
+        self.readers.wait();
+    }
+    /// Handle an interrupt from the UART
+    pub fn handle_irq(&self) {
+        let mut inner = self.inner.exclusive_access();
+        let received = inner.receive();
+        inner.transmit();
+        drop(inner);
+        if received {
+            self.readers.wake_all();
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! virtio over memory-mapped registers
+//!
+//! Both the legacy (version 1) and the modern (version 2) register layouts
+//! are supported. A device gets a single [`VirtQueue`] of [`QUEUE_SIZE`]
+//! descriptors, and requests are completed by polling the used ring, one at
+//! a time.
+
+use alloc::boxed::Box;
+use core::mem::size_of;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+const MAGIC: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const DEVICE_FEATURES: usize = 0x010;
+const DEVICE_FEATURES_SEL: usize = 0x014;
+const DRIVER_FEATURES: usize = 0x020;
+const DRIVER_FEATURES_SEL: usize = 0x024;
+const GUEST_PAGE_SIZE: usize = 0x028; // legacy
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c; // legacy
+const QUEUE_PFN: usize = 0x040; // legacy
+const QUEUE_READY: usize = 0x044; // modern
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+const QUEUE_DESC_LOW: usize = 0x080; // modern
+const QUEUE_DESC_HIGH: usize = 0x084;
+const QUEUE_DRIVER_LOW: usize = 0x090;
+const QUEUE_DRIVER_HIGH: usize = 0x094;
+const QUEUE_DEVICE_LOW: usize = 0x0a0;
+const QUEUE_DEVICE_HIGH: usize = 0x0a4;
+const CONFIG: usize = 0x100;
+
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// bit 32 of the features, which modern devices require
+const FEATURE_VERSION_1: u32 = 1 << 0;
+
+pub const DEVICE_ID_BLOCK: u32 = 2;
+
+/// descriptors in a queue
+pub const QUEUE_SIZE: usize = 16;
+const QUEUE_ALIGNMENT: usize = 4096;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+const AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// starts on the next page, as the legacy layout wants
+#[repr(C, align(4096))]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/// a virtqueue in the legacy layout, which also suits modern devices
+#[repr(C, align(4096))]
+struct QueueLayout {
+    desc: [Descriptor; QUEUE_SIZE],
+    avail: AvailRing,
+    used: UsedRing,
+}
+
+/// A buffer to hand to the device
+pub enum Buffer {
+    /// the device reads `len` bytes at `addr`
+    Readable(usize, usize),
+    /// the device writes up to `len` bytes at `addr`
+    Writable(usize, usize),
+}
+
+/// A virtqueue
+///
+/// The queue lives on the kernel heap, where virtual and physical addresses
+/// are the same, so the device can use the addresses we see. The same goes
+/// for the buffers passed to [`VirtQueue::submit`].
+pub struct VirtQueue {
+    layout: Box<QueueLayout>,
+    /// `used.idx` as of the last completed request
+    last_used: u16,
+}
+
+impl VirtQueue {
+    fn new() -> Self {
+        let mut layout: Box<QueueLayout> = Box::new(unsafe { core::mem::zeroed() });
+        layout.avail.flags = AVAIL_F_NO_INTERRUPT;
+        Self {
+            layout,
+            last_used: 0,
+        }
+    }
+    fn addr(&self) -> usize {
+        &*self.layout as *const QueueLayout as usize
+    }
+    fn avail_addr(&self) -> usize {
+        &self.layout.avail as *const AvailRing as usize
+    }
+    fn used_addr(&self) -> usize {
+        &self.layout.used as *const UsedRing as usize
+    }
+    /// Hand the chain of `buffers` to the device of `regs`, and wait until it
+    /// is done with them
+    pub fn submit(&mut self, regs: &Registers, buffers: &[Buffer]) {
+        assert!(!buffers.is_empty() && buffers.len() <= QUEUE_SIZE);
+        // only one request is ever in flight, so the chain always starts at 0
+        for (i, buffer) in buffers.iter().enumerate() {
+            let (addr, len, mut flags) = match *buffer {
+                Buffer::Readable(addr, len) => (addr, len, 0),
+                Buffer::Writable(addr, len) => (addr, len, DESC_F_WRITE),
+            };
+            if i + 1 < buffers.len() {
+                flags |= DESC_F_NEXT;
+            }
+            self.layout.desc[i] = Descriptor {
+                addr: addr as u64,
+                len: len as u32,
+                flags,
+                next: (i + 1) as u16,
+            };
+        }
+        let avail = &mut self.layout.avail;
+        let idx = unsafe { read_volatile(&avail.idx) };
+        avail.ring[idx as usize % QUEUE_SIZE] = 0;
+        // the descriptors must be visible before the index moves
+        fence(Ordering::SeqCst);
+        unsafe { write_volatile(&mut avail.idx, idx.wrapping_add(1)) };
+        fence(Ordering::SeqCst);
+        regs.write(QUEUE_NOTIFY, 0);
+        while unsafe { read_volatile(&self.layout.used.idx) } == self.last_used {
+            core::hint::spin_loop();
+        }
+        fence(Ordering::SeqCst);
+        self.last_used = self.last_used.wrapping_add(1);
+        // clear any interrupt the device raised anyway
+        let status = regs.read(INTERRUPT_STATUS);
+        regs.write(INTERRUPT_ACK, status);
+    }
+}
+
+/// registers of a virtio-mmio device at `base`
+pub struct Registers {
+    base: usize,
+}
+
+impl Registers {
+    pub fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+    pub fn write(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) };
+    }
+    /// Read the device-specific configuration at `offset`
+    pub fn config<T: Copy>(&self, offset: usize) -> T {
+        assert_eq!(size_of::<T>() % 4, 0);
+        let mut value = core::mem::MaybeUninit::<T>::uninit();
+        let words = value.as_mut_ptr() as *mut u32;
+        for i in 0..size_of::<T>() / 4 {
+            unsafe { words.add(i).write(self.read(CONFIG + offset + i * 4)) };
+        }
+        unsafe { value.assume_init() }
+    }
+}
+
+/// Get the device id of the virtio-mmio slot at `base`, or `None` if there
+/// is no device in it
+///
+/// # Safety
+///
+/// `base` must be the address of a virtio-mmio slot.
+pub unsafe fn probe(base: usize) -> Option<u32> {
+    let regs = Registers { base };
+    if regs.read(MAGIC) != VIRTIO_MAGIC || !matches!(regs.read(VERSION), 1 | 2) {
+        return None;
+    }
+    match regs.read(DEVICE_ID) {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+/// Reset the device at `base` and set up its first queue, accepting none of
+/// its optional features
+///
+/// # Safety
+///
+/// `base` must be the address of a virtio-mmio slot with a device in it.
+pub unsafe fn init(base: usize) -> Result<(Registers, VirtQueue), &'static str> {
+    let regs = Registers { base };
+    let legacy = regs.read(VERSION) == 1;
+    regs.write(STATUS, 0);
+    regs.write(STATUS, STATUS_ACKNOWLEDGE);
+    regs.write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+    // features
+    let mut status = STATUS_ACKNOWLEDGE | STATUS_DRIVER;
+    regs.write(DRIVER_FEATURES_SEL, 0);
+    regs.write(DRIVER_FEATURES, 0);
+    if !legacy {
+        regs.write(DEVICE_FEATURES_SEL, 1);
+        if regs.read(DEVICE_FEATURES) & FEATURE_VERSION_1 == 0 {
+            return Err("modern device without VIRTIO_F_VERSION_1");
+        }
+        regs.write(DRIVER_FEATURES_SEL, 1);
+        regs.write(DRIVER_FEATURES, FEATURE_VERSION_1);
+        status |= STATUS_FEATURES_OK;
+        regs.write(STATUS, status);
+        if regs.read(STATUS) & STATUS_FEATURES_OK == 0 {
+            return Err("features rejected");
+        }
+    } else {
+        regs.write(GUEST_PAGE_SIZE, QUEUE_ALIGNMENT as u32);
+    }
+    // queue 0
+    regs.write(QUEUE_SEL, 0);
+    let max = regs.read(QUEUE_NUM_MAX) as usize;
+    if max < QUEUE_SIZE {
+        return Err("queue too small");
+    }
+    let queue = VirtQueue::new();
+    regs.write(QUEUE_NUM, QUEUE_SIZE as u32);
+    if legacy {
+        regs.write(QUEUE_ALIGN, QUEUE_ALIGNMENT as u32);
+        regs.write(QUEUE_PFN, (queue.addr() / QUEUE_ALIGNMENT) as u32);
+    } else {
+        let split = |addr: usize| (addr as u32, (addr >> 32) as u32);
+        let (low, high) = split(queue.addr());
+        regs.write(QUEUE_DESC_LOW, low);
+        regs.write(QUEUE_DESC_HIGH, high);
+        let (low, high) = split(queue.avail_addr());
+        regs.write(QUEUE_DRIVER_LOW, low);
+        regs.write(QUEUE_DRIVER_HIGH, high);
+        let (low, high) = split(queue.used_addr());
+        regs.write(QUEUE_DEVICE_LOW, low);
+        regs.write(QUEUE_DEVICE_HIGH, high);
+        regs.write(QUEUE_READY, 1);
+    }
+    regs.write(STATUS, status | STATUS_DRIVER_OK);
+    Ok((regs, queue))
+}
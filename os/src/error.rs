@@ -0,0 +1,64 @@
+//! Kernel error codes
+//!
+//! Failures inside the kernel are reported as [`Errno`] values. The numbers
+//! follow Linux, so that syscalls can hand them back to user space by
+//! returning the negated code.
+
+#![allow(dead_code, clippy::upper_case_acronyms)]
+
+#[repr(isize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// error numbers, as in Linux
+pub enum Errno {
+    /// Operation not permitted
+    EPERM = 1,
+    /// No such file or directory
+    ENOENT = 2,
+    /// No such process
+    ESRCH = 3,
+    /// Interrupted system call
+    EINTR = 4,
+    /// I/O error
+    EIO = 5,
+    /// Argument list too long
+    E2BIG = 7,
+    /// Exec format error
+    ENOEXEC = 8,
+    /// Bad file number
+    EBADF = 9,
+    /// No child processes
+    ECHILD = 10,
+    /// Try again
+    EAGAIN = 11,
+    /// Out of memory
+    ENOMEM = 12,
+    /// Permission denied
+    EACCES = 13,
+    /// Bad address
+    EFAULT = 14,
+    /// File exists
+    EEXIST = 17,
+    /// Not a directory
+    ENOTDIR = 20,
+    /// Is a directory
+    EISDIR = 21,
+    /// Invalid argument
+    EINVAL = 22,
+    /// Too many open files
+    EMFILE = 24,
+    /// File too large
+    EFBIG = 27,
+    /// No space left on device
+    ENOSPC = 28,
+    /// Illegal seek
+    ESPIPE = 29,
+    /// Broken pipe
+    EPIPE = 32,
+    /// File name too long
+    ENAMETOOLONG = 36,
+    /// Invalid system call number
+    ENOSYS = 38,
+}
+
+/// result type used throughout the kernel
+pub type KernelResult<T> = Result<T, Errno>;
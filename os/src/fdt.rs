@@ -0,0 +1,160 @@
+//! Flattened device tree parser
+//!
+//! Just enough of the format (see chapter 5 of the Devicetree Specification)
+//! to walk the nodes and properties of the tree the SBI passes to the kernel.
+//! Malformed trees end the walk early rather than panicking.
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_SIZE: usize = 40;
+/// the format version we implement: a blob must be at least this version, for
+/// the size of the structure block, and be backwards compatible with it
+const FDT_VERSION: u32 = 17;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// read the big-endian `u32` at `offset`
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// read the nul-terminated string at `offset`
+fn cstr(bytes: &[u8], offset: usize) -> Option<&str> {
+    let bytes = bytes.get(offset..)?;
+    let len = bytes.iter().position(|&c| c == 0)?;
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// A device tree blob
+pub struct Fdt<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Check the header of the blob at `addr`
+    ///
+    /// # Safety
+    ///
+    /// If `addr` is not null, as many bytes as the header says must be
+    /// readable there.
+    pub unsafe fn from_addr(addr: usize) -> Result<Self, &'static str> {
+        if addr == 0 || addr % 8 != 0 {
+            return Err("bad address");
+        }
+        let header = core::slice::from_raw_parts(addr as *const u8, FDT_HEADER_SIZE);
+        if be32(header, 0) != Some(FDT_MAGIC) {
+            return Err("bad magic");
+        }
+        let total_size = be32(header, 4).unwrap() as usize;
+        Self::new(core::slice::from_raw_parts(addr as *const u8, total_size))
+    }
+    /// Check the header of `blob`
+    pub fn new(blob: &'a [u8]) -> Result<Self, &'static str> {
+        let field = |offset| {
+            be32(blob, offset)
+                .map(|v| v as usize)
+                .ok_or("truncated header")
+        };
+        if field(0)? != FDT_MAGIC as usize {
+            return Err("bad magic");
+        }
+        // `version`, then `last_comp_version`
+        if field(20)? < FDT_VERSION as usize || field(24)? > FDT_VERSION as usize {
+            return Err("unsupported version");
+        }
+        let (off_structs, off_strings) = (field(8)?, field(12)?);
+        let (size_strings, size_structs) = (field(32)?, field(36)?);
+        Ok(Self {
+            structs: blob
+                .get(off_structs..off_structs + size_structs)
+                .ok_or("structure block out of bounds")?,
+            strings: blob
+                .get(off_strings..off_strings + size_strings)
+                .ok_or("strings block out of bounds")?,
+        })
+    }
+    /// Walk the tree, depth first
+    pub fn tokens(&self) -> Tokens<'a> {
+        Tokens {
+            structs: self.structs,
+            strings: self.strings,
+            offset: 0,
+        }
+    }
+}
+
+/// An item of the structure block
+pub enum Token<'a> {
+    /// the start of a node, with its name
+    BeginNode(&'a str),
+    /// the end of the latest node started
+    EndNode,
+    /// a property of the current node, with its name and value
+    Prop(&'a str, &'a [u8]),
+}
+
+/// Iterator over the structure block of an [`Fdt`]
+pub struct Tokens<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn next_token(&mut self) -> Option<Token<'a>> {
+        loop {
+            let token = be32(self.structs, self.offset)?;
+            self.offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(self.structs, self.offset)?;
+                    self.offset = align4(self.offset + name.len() + 1);
+                    return Some(Token::BeginNode(name));
+                }
+                FDT_END_NODE => return Some(Token::EndNode),
+                FDT_PROP => {
+                    let len = be32(self.structs, self.offset)? as usize;
+                    let name_offset = be32(self.structs, self.offset + 4)? as usize;
+                    let start = self.offset + 8;
+                    let value = self.structs.get(start..start + len)?;
+                    let name = cstr(self.strings, name_offset)?;
+                    self.offset = align4(start + len);
+                    return Some(Token::Prop(name, value));
+                }
+                FDT_NOP => continue,
+                // FDT_END, or garbage
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.next_token();
+        if token.is_none() {
+            // stay at the end
+            self.offset = self.structs.len();
+        }
+        token
+    }
+}
+
+/// Read a number made of `cells` big-endian cells at the start of `value`,
+/// returning it and the rest of `value`
+pub fn read_cells(value: &[u8], cells: usize) -> Option<(usize, &[u8])> {
+    let mut n = 0;
+    for i in 0..cells {
+        n = n << 32 | be32(value, i * 4)? as usize;
+    }
+    Some((n, &value[cells * 4..]))
+}
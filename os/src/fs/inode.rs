@@ -0,0 +1,166 @@
+//! Files on the disk
+
+use super::File;
+use crate::drivers::block_device;
+use crate::error::{Errno, KernelResult};
+use crate::sync::UPSafeCell;
+use crate::syscall::UserBuffer;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use bitflags::*;
+use easy_fs::{EasyFileSystem, FsError, Inode};
+use lazy_static::*;
+
+/// A file opened by a task, with its own offset
+pub struct OSInode {
+    readable: bool,
+    writable: bool,
+    inner: UPSafeCell<OSInodeInner>,
+}
+
+/// mutable part of [`OSInode`]
+pub struct OSInodeInner {
+    offset: usize,
+    inode: Arc<Inode>,
+}
+
+impl OSInode {
+    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+        Self {
+            readable,
+            writable,
+            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode }) },
+        }
+    }
+    /// Read from the offset to the end of the file
+    pub fn read_all(&self) -> Vec<u8> {
+        let mut inner = self.inner.exclusive_access();
+        let mut data = vec![0u8; inner.inode.size().saturating_sub(inner.offset)];
+        let len = inner.inode.read_at(inner.offset, &mut data);
+        inner.offset += len;
+        data.truncate(len);
+        data
+    }
+}
+
+impl From<FsError> for Errno {
+    fn from(err: FsError) -> Self {
+        match err {
+            FsError::Exists => Errno::EEXIST,
+            FsError::InvalidName => Errno::ENOENT,
+            FsError::NameTooLong => Errno::ENAMETOOLONG,
+            FsError::NoSpace => Errno::ENOSPC,
+            FsError::TooBig => Errno::EFBIG,
+        }
+    }
+}
+
+lazy_static! {
+    /// the root directory of the filesystem on the disk
+    pub static ref ROOT_INODE: Arc<Inode> = {
+        let device = block_device().expect("no disk found");
+        let efs = EasyFileSystem::open(device).expect("no filesystem found on the disk");
+        Arc::new(EasyFileSystem::root_inode(&efs))
+    };
+}
+
+/// list all apps
+#[allow(unused)]
+pub fn list_apps() {
+    println!("/**** APPS ****");
+    for app in ROOT_INODE.ls() {
+        println!("{}", app);
+    }
+    println!("**************/");
+}
+
+bitflags! {
+    /// flags of `sys_open`
+    pub struct OpenFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        const CREATE = 1 << 9;
+        const TRUNC = 1 << 10;
+    }
+}
+
+impl OpenFlags {
+    /// Whether the file is opened for reading and for writing
+    ///
+    /// Both `WRONLY` and `RDWR` being set is taken as `WRONLY`.
+    pub fn read_write(&self) -> (bool, bool) {
+        if self.is_empty() {
+            (true, false)
+        } else if self.contains(Self::WRONLY) {
+            (false, true)
+        } else {
+            (true, true)
+        }
+    }
+}
+
+/// Open the file `name` in the root directory
+///
+/// Fails with [`Errno::ENOENT`] if there is no such file and `CREATE` is not
+/// set, or with the reason it cannot be created, like [`Errno::ENOSPC`].
+pub fn open_file(name: &str, flags: OpenFlags) -> KernelResult<Arc<OSInode>> {
+    let (readable, writable) = flags.read_write();
+    let inode = match ROOT_INODE.find(name) {
+        Some(inode) => {
+            if flags.contains(OpenFlags::TRUNC) {
+                inode.clear();
+            }
+            inode
+        }
+        None if flags.contains(OpenFlags::CREATE) => ROOT_INODE.create(name)?,
+        None => return Err(Errno::ENOENT),
+    };
+    Ok(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+/// Get the ELF data of the app named `name`
+pub fn read_app(name: &str) -> Option<Vec<u8>> {
+    open_file(name, OpenFlags::RDONLY)
+        .ok()
+        .map(|file| file.read_all())
+}
+
+impl File for OSInode {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> KernelResult<usize> {
+        let mut inner = self.inner.exclusive_access();
+        let mut total_read_size = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            let read_size = inner.inode.read_at(inner.offset, *slice);
+            if read_size == 0 {
+                break;
+            }
+            inner.offset += read_size;
+            total_read_size += read_size;
+        }
+        Ok(total_read_size)
+    }
+    fn write(&self, buf: UserBuffer) -> KernelResult<usize> {
+        let mut inner = self.inner.exclusive_access();
+        let mut total_write_size = 0usize;
+        for slice in buf.buffers.iter() {
+            match inner.inode.write_at(inner.offset, *slice) {
+                Ok(write_size) => {
+                    inner.offset += write_size;
+                    total_write_size += write_size;
+                }
+                // report what was written before the disk filled up
+                Err(_) if total_write_size > 0 => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(total_write_size)
+    }
+}
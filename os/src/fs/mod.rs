@@ -0,0 +1,31 @@
+//! Files
+//!
+//! Everything a task can read or write through a file descriptor implements
+//! [`File`]: the console with [`Stdin`] and [`Stdout`], files on the disk
+//! with [`OSInode`], and the ends of a [`Pipe`].
+//!
+//! The disk found by [`crate::drivers`] holds an easy-fs filesystem, packed
+//! on the host by `easy-fs-fuse`. Its root directory has the apps in it,
+//! which [`read_app()`] looks up by name.
+
+mod inode;
+mod pipe;
+mod stdio;
+
+use crate::error::KernelResult;
+use crate::syscall::UserBuffer;
+
+/// Something that can be read or written through a file descriptor
+pub trait File: Send + Sync {
+    fn readable(&self) -> bool;
+    fn writable(&self) -> bool;
+    /// Read into `buf`, returning the number of bytes read, 0 meaning end of
+    /// file
+    fn read(&self, buf: UserBuffer) -> KernelResult<usize>;
+    /// Write `buf`, returning the number of bytes written
+    fn write(&self, buf: UserBuffer) -> KernelResult<usize>;
+}
+
+pub use inode::{list_apps, open_file, read_app, OSInode, OpenFlags, ROOT_INODE};
+pub use pipe::{make_pipe, Pipe};
+pub use stdio::{Stdin, Stdout};
@@ -0,0 +1,160 @@
+//! Anonymous pipes
+//!
+//! The two ends of a pipe share a bounded ring buffer. Readers block while it
+//! is empty and writers while it is full. Once every write end is closed,
+//! readers get end of file, and once every read end is closed, writers get
+//! [`Errno::EPIPE`].
+
+use super::File;
+use crate::error::{Errno, KernelResult};
+use crate::sync::{UPSafeCell, WaitQueue};
+use crate::syscall::UserBuffer;
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+
+/// capacity of a pipe, in bytes
+const PIPE_BUFFER_SIZE: usize = 4096;
+
+/// One end of a pipe
+pub struct Pipe {
+    readable: bool,
+    writable: bool,
+    shared: Arc<PipeShared>,
+}
+
+/// state shared by both ends of a pipe
+struct PipeShared {
+    buffer: UPSafeCell<PipeRingBuffer>,
+    /// readers waiting for data
+    readers: WaitQueue,
+    /// writers waiting for room
+    writers: WaitQueue,
+}
+
+struct PipeRingBuffer {
+    data: VecDeque<u8>,
+    /// the ends are reference counted by the fd tables holding them, so they
+    /// are all closed once these fail to upgrade
+    read_end: Weak<Pipe>,
+    write_end: Weak<Pipe>,
+}
+
+impl PipeRingBuffer {
+    fn all_read_ends_closed(&self) -> bool {
+        self.read_end.upgrade().is_none()
+    }
+    fn all_write_ends_closed(&self) -> bool {
+        self.write_end.upgrade().is_none()
+    }
+}
+
+/// Create a pipe, returning its read end and its write end
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let shared = Arc::new(PipeShared {
+        buffer: unsafe {
+            UPSafeCell::new(PipeRingBuffer {
+                data: VecDeque::with_capacity(PIPE_BUFFER_SIZE),
+                read_end: Weak::new(),
+                write_end: Weak::new(),
+            })
+        },
+        readers: WaitQueue::new(),
+        writers: WaitQueue::new(),
+    });
+    let read_end = Arc::new(Pipe {
+        readable: true,
+        writable: false,
+        shared: shared.clone(),
+    });
+    let write_end = Arc::new(Pipe {
+        readable: false,
+        writable: true,
+        shared: shared.clone(),
+    });
+    let mut buffer = shared.buffer.exclusive_access();
+    buffer.read_end = Arc::downgrade(&read_end);
+    buffer.write_end = Arc::downgrade(&write_end);
+    drop(buffer);
+    (read_end, write_end)
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    /// Block until there is data, and read as much of it as fits
+    fn read(&self, buf: UserBuffer) -> KernelResult<usize> {
+        if !self.readable {
+            return Err(Errno::EBADF);
+        }
+        let want = buf.len();
+        if want == 0 {
+            return Ok(0);
+        }
+        loop {
+            let mut buffer = self.shared.buffer.exclusive_access();
+            if buffer.data.is_empty() {
+                if buffer.all_write_ends_closed() {
+                    return Ok(0);
+                }
+                drop(buffer);
+                self.shared.readers.wait();
+                continue;
+            }
+            let len = want.min(buffer.data.len());
+            let dst = buf.buffers.into_iter().flat_map(|slice| slice.iter_mut());
+            for (byte, b) in dst.zip(buffer.data.drain(..len)) {
+                *byte = b;
+            }
+            drop(buffer);
+            self.shared.writers.wake_all();
+            return Ok(len);
+        }
+    }
+    /// Write all of `buf`, blocking whenever the pipe is full
+    fn write(&self, buf: UserBuffer) -> KernelResult<usize> {
+        if !self.writable {
+            return Err(Errno::EBADF);
+        }
+        let total = buf.len();
+        let mut bytes = buf.buffers.into_iter().flat_map(|slice| slice.iter());
+        let mut written = 0;
+        loop {
+            let mut buffer = self.shared.buffer.exclusive_access();
+            if buffer.all_read_ends_closed() {
+                return if written == 0 {
+                    Err(Errno::EPIPE)
+                } else {
+                    Ok(written)
+                };
+            }
+            let len = (PIPE_BUFFER_SIZE - buffer.data.len()).min(total - written);
+            buffer.data.extend(bytes.by_ref().take(len));
+            written += len;
+            drop(buffer);
+            if len > 0 {
+                self.shared.readers.wake_all();
+            }
+            if written == total {
+                return Ok(written);
+            }
+            self.shared.writers.wait();
+        }
+    }
+}
+
+impl Drop for Pipe {
+    /// Let tasks blocked on the other end see that this end is gone, in case
+    /// it was the last one
+    fn drop(&mut self) {
+        if self.writable {
+            self.shared.readers.wake_all();
+        }
+        if self.readable {
+            self.shared.writers.wake_all();
+        }
+    }
+}
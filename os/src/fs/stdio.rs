@@ -0,0 +1,55 @@
+//! The console as a file
+
+use super::File;
+use crate::console::{putbytes, read_input, wait_for_input};
+use crate::error::{Errno, KernelResult};
+use crate::syscall::UserBuffer;
+
+/// Input from the console, read line by line
+pub struct Stdin;
+
+/// Output to the console, used for both stdout and stderr
+pub struct Stdout;
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Block until a whole line has been typed, and return at most one line
+    fn read(&self, mut buf: UserBuffer) -> KernelResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(data) = read_input(buf.len()) {
+                return Ok(buf.fill(&data));
+            }
+            wait_for_input();
+        }
+    }
+    fn write(&self, _buf: UserBuffer) -> KernelResult<usize> {
+        Err(Errno::EBADF)
+    }
+}
+
+impl File for Stdout {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _buf: UserBuffer) -> KernelResult<usize> {
+        Err(Errno::EBADF)
+    }
+    /// Write the bytes as they are, which need not be valid UTF-8
+    fn write(&self, buf: UserBuffer) -> KernelResult<usize> {
+        for buffer in buf.buffers.iter() {
+            putbytes(buffer);
+        }
+        Ok(buf.len())
+    }
+}
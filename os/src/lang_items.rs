@@ -1,11 +1,14 @@
 //! The panic handler
 
+use crate::console::switch_to_sbi;
 use crate::sbi::shutdown;
 use core::panic::PanicInfo;
 
 #[panic_handler]
 /// panic handler
 fn panic(info: &PanicInfo) -> ! {
+    // the UART driver may be in use by whatever panicked
+    switch_to_sbi();
     if let Some(location) = info.location() {
         println!(
             "Panicked at {}:{} {}",
@@ -16,5 +19,5 @@ fn panic(info: &PanicInfo) -> ! {
     } else {
         println!("[kernel] Panicked: {}", info.message().unwrap());
     }
-    shutdown()
+    shutdown(true)
 }
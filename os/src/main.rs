@@ -6,14 +6,16 @@
 //! - [`trap`]: Handles all cases of switching from userspace to the kernel
 //! - [`task`]: Task management
 //! - [`syscall`]: System call handling and implementation
+//! - [`mm`]: Address map using SV39
+//! - [`fs`]: Apps and other files on the disk
 //!
 //! The operating system also starts in this module. Kernel code starts
 //! executing from `entry.asm`, after which [`rust_main()`] is called to
 //! initialize various pieces of functionality. (See its source code for
 //! details.)
 //!
-//! We then call [`task::run_first_task()`] and for the first time go to
-//! userspace.
+//! We then add the init process with [`task::add_initproc()`], and for the
+//! first time go to userspace in [`task::run_tasks()`].
 
 #![no_std]
 #![no_main]
@@ -27,11 +29,16 @@ extern crate alloc;
 
 #[macro_use]
 mod console;
+mod board;
 mod config;
+mod drivers;
+mod error;
+mod fdt;
+mod fs;
 mod heap_alloc;
 mod lang_items;
-mod loader;
 mod logging;
+pub mod mm;
 mod sbi;
 mod sync;
 pub mod syscall;
@@ -40,7 +47,6 @@ mod timer;
 pub mod trap;
 
 core::arch::global_asm!(include_str!("entry.asm"));
-core::arch::global_asm!(include_str!("link_app.S"));
 
 /// clear BSS segment
 fn clear_bss() {
@@ -56,15 +62,26 @@ fn clear_bss() {
 
 #[no_mangle]
 /// the rust entry-point of os
-pub fn rust_main() -> ! {
+pub fn rust_main(_hartid: usize, dtb: usize) -> ! {
     clear_bss();
     logging::init();
     println!("[kernel] Hello, world!");
     heap_alloc::init_heap();
+    // before the frame allocator takes over the memory the device tree is in
+    board::init(dtb);
+    mm::init();
+    #[cfg(feature = "self-test")]
+    mm::remap_test();
     trap::init();
-    loader::load_apps();
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
-    task::run_first_task();
+    drivers::init();
+    console::switch_to_uart();
+    #[cfg(feature = "block-test")]
+    drivers::block_device_test();
+    #[cfg(feature = "self-test")]
+    fs::list_apps();
+    task::add_initproc();
+    task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
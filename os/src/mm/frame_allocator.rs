@@ -0,0 +1,166 @@
+//! Implementation of [`FrameAllocator`] which
+//! controls all the frames in the operating system.
+//!
+//! Every frame between the end of the kernel image (`ekernel` in
+//! `linker.ld`) and the end of RAM is managed here. Frames are handed out as
+//! [`FrameTracker`]s, which give the frame back when dropped.
+
+use super::{PhysAddr, PhysPageNum};
+use crate::board::board;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Formatter};
+use lazy_static::*;
+
+/// manage a frame which has the same lifecycle as the tracker
+pub struct FrameTracker {
+    pub ppn: PhysPageNum,
+}
+
+impl FrameTracker {
+    /// Take ownership of `ppn`, zeroing the frame
+    pub fn new(ppn: PhysPageNum) -> Self {
+        let bytes_array = ppn.get_bytes_array();
+        for i in bytes_array {
+            *i = 0;
+        }
+        Self { ppn }
+    }
+}
+
+impl Debug for FrameTracker {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_fmt(format_args!("FrameTracker:PPN={:#x}", self.ppn.0))
+    }
+}
+
+impl Drop for FrameTracker {
+    fn drop(&mut self) {
+        frame_dealloc(self.ppn);
+    }
+}
+
+trait FrameAllocator {
+    fn new() -> Self;
+    fn alloc(&mut self) -> Option<PhysPageNum>;
+    fn dealloc(&mut self, ppn: PhysPageNum);
+    /// number of frames managed by the allocator
+    fn total(&self) -> usize;
+    /// number of frames that can still be allocated
+    fn free(&self) -> usize;
+}
+
+/// an implementation for frame allocator
+///
+/// Frames in `[current, end)` have never been handed out, while `recycled`
+/// holds frames that were given back.
+pub struct StackFrameAllocator {
+    start: usize,
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+}
+
+impl StackFrameAllocator {
+    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.start = l.0;
+        self.current = l.0;
+        self.end = r.0;
+        info!(
+            "frame allocator: {} frames in [{:#x}, {:#x})",
+            self.total(),
+            PhysAddr::from(l).0,
+            PhysAddr::from(r).0
+        );
+    }
+}
+
+impl FrameAllocator for StackFrameAllocator {
+    fn new() -> Self {
+        Self {
+            start: 0,
+            current: 0,
+            end: 0,
+            recycled: Vec::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        if let Some(ppn) = self.recycled.pop() {
+            Some(ppn.into())
+        } else if self.current == self.end {
+            None
+        } else {
+            self.current += 1;
+            Some((self.current - 1).into())
+        }
+    }
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        let ppn = ppn.0;
+        // validity check
+        if ppn < self.start || ppn >= self.current || self.recycled.iter().any(|v| *v == ppn) {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn);
+        }
+        // recycle
+        self.recycled.push(ppn);
+    }
+    fn total(&self) -> usize {
+        self.end - self.start
+    }
+    fn free(&self) -> usize {
+        self.end - self.current + self.recycled.len()
+    }
+}
+
+type FrameAllocatorImpl = StackFrameAllocator;
+
+lazy_static! {
+    /// frame allocator instance through lazy_static!
+    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
+        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+}
+
+/// initiate the frame allocator using `ekernel` and the end of RAM
+pub fn init_frame_allocator() {
+    extern "C" {
+        fn ekernel();
+    }
+    FRAME_ALLOCATOR.exclusive_access().init(
+        PhysAddr::from(ekernel as usize).ceil(),
+        PhysAddr::from(board().memory_end).floor(),
+    );
+}
+
+/// allocate a frame
+pub fn frame_alloc() -> Option<FrameTracker> {
+    FRAME_ALLOCATOR
+        .exclusive_access()
+        .alloc()
+        .map(FrameTracker::new)
+}
+
+/// deallocate a frame
+fn frame_dealloc(ppn: PhysPageNum) {
+    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
+}
+
+#[derive(Copy, Clone, Debug)]
+/// frame usage, counted in frames
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+}
+
+impl FrameStats {
+    pub fn used(&self) -> usize {
+        self.total - self.free
+    }
+}
+
+/// get the current frame usage
+pub fn frame_stats() -> FrameStats {
+    let allocator = FRAME_ALLOCATOR.exclusive_access();
+    FrameStats {
+        total: allocator.total(),
+        free: allocator.free(),
+    }
+}
@@ -0,0 +1,655 @@
+//! Implementation of [`MapArea`] and [`MemorySet`].
+
+use super::{frame_alloc, FrameTracker};
+use super::{PTEFlags, PageTable, PageTableEntry};
+use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
+use super::{StepByOne, VPNRange};
+use crate::config::{
+    APP_SIZE_LIMIT, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE,
+};
+use crate::board::board;
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+use lazy_static::*;
+use riscv::register::satp;
+use xmas_elf::header;
+
+/// Loaded segments must end below this address, leaving room for a guard page
+/// and the user stack in the lower half of the Sv39 address space.
+const USER_SPACE_END: usize = (1 << 38) - PAGE_SIZE - USER_STACK_SIZE;
+
+/// Reason of the failure of [`MemorySet::from_elf`] when frames run out
+const OUT_OF_MEMORY: &str = "out of memory";
+
+extern "C" {
+    fn stext();
+    fn etext();
+    fn srodata();
+    fn erodata();
+    fn sdata();
+    fn edata();
+    fn sbss_with_stack();
+    fn ebss();
+    fn ekernel();
+    fn strampoline();
+}
+
+lazy_static! {
+    /// a memory set instance through lazy_static! managing kernel space
+    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
+        Arc::new(unsafe {
+            UPSafeCell::new(MemorySet::new_kernel().expect("no memory for kernel space"))
+        });
+}
+
+/// memory set structure, controls virtual-memory space
+pub struct MemorySet {
+    page_table: PageTable,
+    areas: Vec<MapArea>,
+    /// anonymous memory mapped by `sys_mmap`, which is all `sys_munmap` may
+    /// unmap
+    mmap_areas: Vec<MapArea>,
+}
+
+impl MemorySet {
+    pub fn new_bare() -> Option<Self> {
+        Some(Self {
+            page_table: PageTable::new()?,
+            areas: Vec::new(),
+            mmap_areas: Vec::new(),
+        })
+    }
+    pub fn token(&self) -> usize {
+        self.page_table.token()
+    }
+    /// Assume that no conflicts.
+    ///
+    /// Returns `None` and maps nothing if frames run out.
+    pub fn insert_framed_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> Option<()> {
+        self.push(
+            MapArea::new(start_va, end_va, MapType::Framed, permission),
+            None,
+        )
+    }
+    /// Unmap and drop the area starting at `start_vpn`, if any
+    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
+        if let Some((idx, area)) = self
+            .areas
+            .iter_mut()
+            .enumerate()
+            .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
+        {
+            area.unmap(&mut self.page_table);
+            self.areas.remove(idx);
+        }
+    }
+    /// Whether any area covers a page in `[start, end)`
+    fn overlaps(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.areas
+            .iter()
+            .chain(self.mmap_areas.iter())
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end()))
+            // an empty area, like a fresh heap, covers nothing
+            .any(|(l, r)| l < r && l < end && start < r)
+    }
+    /// Map fresh zeroed frames at `[start, end)` with `permission`.
+    ///
+    /// Returns `false` and maps nothing if any of the pages is already mapped,
+    /// or if frames run out.
+    pub fn mmap(
+        &mut self,
+        start: VirtPageNum,
+        end: VirtPageNum,
+        permission: MapPermission,
+    ) -> bool {
+        if self.overlaps(start, end) {
+            return false;
+        }
+        let mut map_area = MapArea::new(start.into(), end.into(), MapType::Framed, permission);
+        if map_area.map(&mut self.page_table).is_none() {
+            return false;
+        }
+        self.mmap_areas.push(map_area);
+        true
+    }
+    /// Unmap `[start, end)`, splitting the areas mapped by [`Self::mmap`]
+    /// as needed.
+    ///
+    /// Returns `false` and unmaps nothing unless every page in the range was
+    /// mapped by [`Self::mmap`].
+    pub fn munmap(&mut self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        // areas never overlap, so they cover the range if they cover as many
+        // pages of it
+        let covered: usize = self
+            .mmap_areas
+            .iter()
+            .map(|area| {
+                let l = area.vpn_range.get_start().max(start);
+                let r = area.vpn_range.get_end().min(end);
+                r.0.saturating_sub(l.0)
+            })
+            .sum();
+        if covered != end.0 - start.0 {
+            return false;
+        }
+        let mut i = 0;
+        while i < self.mmap_areas.len() {
+            let area_start = self.mmap_areas[i].vpn_range.get_start();
+            let area_end = self.mmap_areas[i].vpn_range.get_end();
+            if area_end <= start || end <= area_start {
+                i += 1;
+                continue;
+            }
+            // pieces outside the range go back to the end of the list, and
+            // are skipped when we get there
+            let mut area = self.mmap_areas.swap_remove(i);
+            if area_start < start {
+                let tail = area.split_off(start);
+                self.mmap_areas.push(area);
+                area = tail;
+            }
+            if end < area_end {
+                let tail = area.split_off(end);
+                self.mmap_areas.push(tail);
+            }
+            area.unmap(&mut self.page_table);
+        }
+        true
+    }
+    /// Move the end of the area starting at `start` down to `new_end`,
+    /// unmapping the pages above it.
+    ///
+    /// Returns `false` if there is no such area.
+    pub fn shrink_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
+        let start = start.floor();
+        match self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() == start)
+        {
+            Some(area) => {
+                area.shrink_to(&mut self.page_table, new_end.ceil());
+                true
+            }
+            None => false,
+        }
+    }
+    /// Move the end of the area starting at `start` up to `new_end`, mapping
+    /// fresh zeroed frames.
+    ///
+    /// Returns `false` if there is no such area, if any of the new pages is
+    /// already mapped, or if frames run out.
+    pub fn append_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
+        let start = start.floor();
+        let idx = match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start)
+        {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let end = self.areas[idx].vpn_range.get_end();
+        if end < new_end.ceil() && self.overlaps(end, new_end.ceil()) {
+            return false;
+        }
+        self.areas[idx]
+            .append_to(&mut self.page_table, new_end.ceil())
+            .is_some()
+    }
+    /// Map `map_area` and add it, or return `None` if frames run out
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) -> Option<()> {
+        self.push_with_offset(map_area, 0, data)
+    }
+    /// Like [`MemorySet::push`], with `data` starting `offset` bytes into the
+    /// first page of the area
+    fn push_with_offset(
+        &mut self,
+        mut map_area: MapArea,
+        offset: usize,
+        data: Option<&[u8]>,
+    ) -> Option<()> {
+        map_area.map(&mut self.page_table)?;
+        if let Some(data) = data {
+            map_area.copy_data(&mut self.page_table, data, offset);
+        }
+        self.areas.push(map_area);
+        Some(())
+    }
+    /// Mention that trampoline is not collected by areas.
+    fn map_trampoline(&mut self) -> Option<()> {
+        self.page_table.map(
+            VirtAddr::from(TRAMPOLINE).into(),
+            PhysAddr::from(strampoline as usize).into(),
+            PTEFlags::R | PTEFlags::X,
+        )
+    }
+    /// Without kernel stacks.
+    pub fn new_kernel() -> Option<Self> {
+        let board = board();
+        let mut memory_set = Self::new_bare()?;
+        // map trampoline
+        memory_set.map_trampoline()?;
+        // map kernel sections
+        debug!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
+        debug!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
+        debug!(".data [{:#x}, {:#x})", sdata as usize, edata as usize);
+        debug!(
+            ".bss [{:#x}, {:#x})",
+            sbss_with_stack as usize, ebss as usize
+        );
+        memory_set.push(
+            MapArea::new(
+                (stext as usize).into(),
+                (etext as usize).into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::X,
+            ),
+            None,
+        )?;
+        memory_set.push(
+            MapArea::new(
+                (srodata as usize).into(),
+                (erodata as usize).into(),
+                MapType::Identical,
+                MapPermission::R,
+            ),
+            None,
+        )?;
+        memory_set.push(
+            MapArea::new(
+                (sdata as usize).into(),
+                (edata as usize).into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::W,
+            ),
+            None,
+        )?;
+        memory_set.push(
+            MapArea::new(
+                (sbss_with_stack as usize).into(),
+                (ebss as usize).into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::W,
+            ),
+            None,
+        )?;
+        memory_set.push(
+            MapArea::new(
+                (ekernel as usize).into(),
+                board.memory_end.into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::W,
+            ),
+            None,
+        )?;
+        // map device registers
+        for mmio in board.mmio() {
+            debug!("mmio [{:#x}, {:#x})", mmio.base, mmio.base + mmio.size);
+            memory_set.push(
+                MapArea::new(
+                    mmio.base.into(),
+                    (mmio.base + mmio.size).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            )?;
+        }
+        Some(memory_set)
+    }
+    /// Include sections in elf and trampoline and TrapContext and user stack,
+    /// with an empty heap right above the stack, also returns user_sp, which
+    /// is where the heap starts, and entry point.
+    ///
+    /// Malformed images, and images that do not fit in [`APP_SIZE_LIMIT`],
+    /// are rejected with the reason of the failure.
+    pub fn from_elf(elf_data: &[u8]) -> Result<(Self, usize, usize), &'static str> {
+        let mut memory_set = Self::new_bare().ok_or(OUT_OF_MEMORY)?;
+        // map trampoline
+        memory_set.map_trampoline().ok_or(OUT_OF_MEMORY)?;
+        // map program headers of elf, with U flag
+        let elf = xmas_elf::ElfFile::new(elf_data)?;
+        let elf_header = elf.header;
+        let magic = elf_header.pt1.magic;
+        if magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return Err("invalid elf");
+        }
+        if elf_header.pt1.class() != header::Class::SixtyFour
+            || elf_header.pt2.machine().as_machine() != header::Machine::RISC_V
+        {
+            return Err("not a riscv64 elf");
+        }
+        let ph_count = elf_header.pt2.ph_count();
+        let mut max_end_vpn = VirtPageNum(0);
+        let mut image_size = 0;
+        for i in 0..ph_count {
+            let ph = elf.program_header(i)?;
+            if ph.get_type()? != xmas_elf::program::Type::Load {
+                continue;
+            }
+            let start = ph.virtual_addr() as usize;
+            let mem_size = ph.mem_size() as usize;
+            let file_size = ph.file_size() as usize;
+            let offset = ph.offset() as usize;
+            if file_size > mem_size
+                || offset.checked_add(file_size).map_or(true, |end| end > elf_data.len())
+            {
+                return Err("segment out of file bounds");
+            }
+            let end = match start.checked_add(mem_size) {
+                Some(end) if end <= USER_SPACE_END => end,
+                _ => return Err("segment out of user space"),
+            };
+            let start_va: VirtAddr = start.into();
+            let end_va: VirtAddr = end.into();
+            if start_va.floor() < max_end_vpn {
+                return Err("overlapping or unordered segments");
+            }
+            image_size += (end_va.ceil().0 - start_va.floor().0) * PAGE_SIZE;
+            if image_size > APP_SIZE_LIMIT {
+                return Err("image too large");
+            }
+            let mut map_perm = MapPermission::U;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() {
+                map_perm |= MapPermission::R;
+            }
+            if ph_flags.is_write() {
+                map_perm |= MapPermission::W;
+            }
+            if ph_flags.is_execute() {
+                map_perm |= MapPermission::X;
+            }
+            let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+            max_end_vpn = map_area.vpn_range.get_end();
+            // segments need not start at a page boundary
+            memory_set
+                .push_with_offset(
+                    map_area,
+                    start_va.page_offset(),
+                    Some(&elf_data[offset..offset + file_size]),
+                )
+                .ok_or(OUT_OF_MEMORY)?;
+        }
+        let entry_point = elf_header.pt2.entry_point() as usize;
+        if max_end_vpn.0 == 0 || entry_point >= VirtAddr::from(max_end_vpn).0 {
+            return Err("bad entry point");
+        }
+        // map user stack with U flags
+        let max_end_va: VirtAddr = max_end_vpn.into();
+        let mut user_stack_bottom: usize = max_end_va.into();
+        // guard page
+        user_stack_bottom += PAGE_SIZE;
+        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        memory_set
+            .push(
+                MapArea::new(
+                    user_stack_bottom.into(),
+                    user_stack_top.into(),
+                    MapType::Framed,
+                    MapPermission::R | MapPermission::W | MapPermission::U,
+                ),
+                None,
+            )
+            .ok_or(OUT_OF_MEMORY)?;
+        // the heap starts empty right above the user stack, and grows with
+        // `sys_sbrk`
+        memory_set
+            .push(
+                MapArea::new(
+                    user_stack_top.into(),
+                    user_stack_top.into(),
+                    MapType::Framed,
+                    MapPermission::R | MapPermission::W | MapPermission::U,
+                ),
+                None,
+            )
+            .ok_or(OUT_OF_MEMORY)?;
+        // map TrapContext
+        memory_set
+            .push(
+                MapArea::new(
+                    TRAP_CONTEXT.into(),
+                    TRAMPOLINE.into(),
+                    MapType::Framed,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            )
+            .ok_or(OUT_OF_MEMORY)?;
+        Ok((memory_set, user_stack_top, entry_point))
+    }
+    /// Copy an identical user space, data included, or return `None` if
+    /// frames run out
+    pub fn from_existed_user(user_space: &MemorySet) -> Option<MemorySet> {
+        let mut memory_set = Self::new_bare()?;
+        // map trampoline
+        memory_set.map_trampoline()?;
+        // copy data sections/trap_context/user_stack
+        for area in user_space.areas.iter() {
+            let new_area = MapArea::from_another(area);
+            memory_set.push(new_area, None)?;
+            memory_set.copy_pages_from(user_space, area.vpn_range);
+        }
+        // and anonymous memory
+        for area in user_space.mmap_areas.iter() {
+            let mut new_area = MapArea::from_another(area);
+            new_area.map(&mut memory_set.page_table)?;
+            memory_set.mmap_areas.push(new_area);
+            memory_set.copy_pages_from(user_space, area.vpn_range);
+        }
+        Some(memory_set)
+    }
+    /// Copy the data of `vpn_range` from `another`, where it is mapped too
+    fn copy_pages_from(&mut self, another: &MemorySet, vpn_range: VPNRange) {
+        for vpn in vpn_range {
+            let src_ppn = another.translate(vpn).unwrap().ppn();
+            let dst_ppn = self.translate(vpn).unwrap().ppn();
+            dst_ppn
+                .get_bytes_array()
+                .copy_from_slice(src_ppn.get_bytes_array());
+        }
+    }
+    /// Switch to this address space
+    pub fn activate(&self) {
+        let satp = self.page_table.token();
+        unsafe {
+            satp::write(satp);
+            core::arch::asm!("sfence.vma");
+        }
+    }
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        self.page_table.translate(vpn)
+    }
+    /// Give back all frames owned by the areas of this address space.
+    ///
+    /// Frames of the page table itself are kept, since we might still be
+    /// using it. The address space must not be activated again.
+    pub fn recycle_data_pages(&mut self) {
+        self.areas.clear();
+        self.mmap_areas.clear();
+    }
+}
+
+/// map area structure, controls a contiguous piece of virtual memory
+pub struct MapArea {
+    vpn_range: VPNRange,
+    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    map_type: MapType,
+    map_perm: MapPermission,
+}
+
+impl MapArea {
+    pub fn new(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_type: MapType,
+        map_perm: MapPermission,
+    ) -> Self {
+        let start_vpn: VirtPageNum = start_va.floor();
+        let end_vpn: VirtPageNum = end_va.ceil();
+        Self {
+            vpn_range: VPNRange::new(start_vpn, end_vpn),
+            data_frames: BTreeMap::new(),
+            map_type,
+            map_perm,
+        }
+    }
+    /// An area covering the same pages as `another`, with no frames yet
+    pub fn from_another(another: &MapArea) -> Self {
+        Self {
+            vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
+            data_frames: BTreeMap::new(),
+            map_type: another.map_type,
+            map_perm: another.map_perm,
+        }
+    }
+    /// Unmap the pages from `new_end` on
+    pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        let start = self.vpn_range.get_start();
+        for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
+            self.unmap_one(page_table, vpn)
+        }
+        self.vpn_range = VPNRange::new(start, new_end);
+    }
+    /// Map the pages up to `new_end`, or none of them if frames run out
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> Option<()> {
+        let start = self.vpn_range.get_start();
+        self.map_range(page_table, VPNRange::new(self.vpn_range.get_end(), new_end))?;
+        self.vpn_range = VPNRange::new(start, new_end);
+        Some(())
+    }
+    /// Keep the pages below `at` and return an area with the rest, frames
+    /// included
+    pub fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let (start, end) = (self.vpn_range.get_start(), self.vpn_range.get_end());
+        assert!(start < at && at < end);
+        self.vpn_range = VPNRange::new(start, at);
+        Self {
+            vpn_range: VPNRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+        }
+    }
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<()> {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        match self.map_type {
+            MapType::Identical => page_table.map(vpn, PhysPageNum(vpn.0), pte_flags),
+            MapType::Framed => {
+                let frame = frame_alloc()?;
+                page_table.map(vpn, frame.ppn, pte_flags)?;
+                self.data_frames.insert(vpn, frame);
+                Some(())
+            }
+        }
+    }
+    pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        #[allow(clippy::single_match)]
+        match self.map_type {
+            MapType::Framed => {
+                self.data_frames.remove(&vpn);
+            }
+            _ => {}
+        }
+        page_table.unmap(vpn);
+    }
+    /// Map the whole area, or nothing if frames run out
+    pub fn map(&mut self, page_table: &mut PageTable) -> Option<()> {
+        self.map_range(page_table, self.vpn_range)
+    }
+    /// Map the pages in `range`, or none of them if frames run out
+    fn map_range(&mut self, page_table: &mut PageTable, range: VPNRange) -> Option<()> {
+        for vpn in range {
+            if self.map_one(page_table, vpn).is_none() {
+                for mapped in VPNRange::new(range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return None;
+            }
+        }
+        Some(())
+    }
+    pub fn unmap(&mut self, page_table: &mut PageTable) {
+        for vpn in self.vpn_range {
+            self.unmap_one(page_table, vpn);
+        }
+    }
+    /// data: starting `offset` bytes into the first page, maybe with shorter
+    /// length than the area
+    /// assume that all frames were cleared before
+    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8], offset: usize) {
+        assert_eq!(self.map_type, MapType::Framed);
+        assert!(offset < PAGE_SIZE);
+        let mut start: usize = 0;
+        let mut page_offset = offset;
+        let mut current_vpn = self.vpn_range.get_start();
+        let len = data.len();
+        loop {
+            let src = &data[start..len.min(start + PAGE_SIZE - page_offset)];
+            let dst = &mut page_table
+                .translate(current_vpn)
+                .unwrap()
+                .ppn()
+                .get_bytes_array()[page_offset..page_offset + src.len()];
+            dst.copy_from_slice(src);
+            start += src.len();
+            if start >= len {
+                break;
+            }
+            page_offset = 0;
+            current_vpn.step();
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// map type for memory set: identical or framed
+pub enum MapType {
+    Identical,
+    Framed,
+}
+
+bitflags! {
+    /// map permission corresponding to that in pte: `R W X U`
+    pub struct MapPermission: u8 {
+        const R = 1 << 1;
+        const W = 1 << 2;
+        const X = 1 << 3;
+        const U = 1 << 4;
+    }
+}
+
+/// Check that the kernel sections are mapped with the right permissions
+#[allow(unused)]
+pub fn remap_test() {
+    let kernel_space = KERNEL_SPACE.exclusive_access();
+    let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
+    let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
+    let mid_data: VirtAddr = ((sdata as usize + edata as usize) / 2).into();
+    assert!(!kernel_space
+        .page_table
+        .translate(mid_text.floor())
+        .unwrap()
+        .writable());
+    assert!(!kernel_space
+        .page_table
+        .translate(mid_rodata.floor())
+        .unwrap()
+        .writable());
+    assert!(!kernel_space
+        .page_table
+        .translate(mid_data.floor())
+        .unwrap()
+        .executable());
+    info!("remap_test passed!");
+}
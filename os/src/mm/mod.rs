@@ -0,0 +1,29 @@
+//! Memory management implementation
+//!
+//! SV39 page-based virtual-memory architecture for RV64 systems, and
+//! everything about memory management, like frame allocator, page table,
+//! map area and memory set, is implemented here.
+//!
+//! Every task gets its own [`MemorySet`], while the kernel runs in
+//! [`KERNEL_SPACE`], which maps all physical memory to the same addresses.
+
+mod address;
+mod frame_allocator;
+mod memory_set;
+mod page_table;
+
+pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use address::VPNRange;
+pub use frame_allocator::{frame_alloc, frame_stats, FrameStats, FrameTracker};
+pub use memory_set::remap_test;
+pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
+use page_table::PTEFlags;
+pub use page_table::{PageTable, PageTableEntry};
+
+/// initiate the frame allocator, and switch to the kernel address space
+///
+/// The kernel heap must be usable before this is called.
+pub fn init() {
+    frame_allocator::init_frame_allocator();
+    KERNEL_SPACE.exclusive_access().activate();
+}
@@ -7,6 +7,13 @@ const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
 const SBI_SHUTDOWN: usize = 8;
 
+/// System Reset extension
+const SBI_EXT_SRST: usize = 0x5352_5354;
+const SBI_SRST_RESET: usize = 0;
+const SRST_TYPE_SHUTDOWN: usize = 0;
+const SRST_REASON_NONE: usize = 0;
+const SRST_REASON_FAILURE: usize = 1;
+
 #[inline(always)]
 /// general sbi call
 fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
@@ -23,6 +30,21 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+/// sbi call to function `fid` of extension `eid`, returning `(error, value)`
+fn sbi_call_ext(eid: usize, fid: usize, arg0: usize, arg1: usize) -> (isize, usize) {
+    let (error, value);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") arg0 => error,
+            inlateout("x11") arg1 => value,
+            in("x16") fid,
+            in("x17") eid,
+        );
+    }
+    (error, value)
+}
+
 /// use sbi call to set timer
 pub fn set_timer(timer: usize) {
     sbi_call(SBI_SET_TIMER, timer, 0, 0);
@@ -39,7 +61,22 @@ pub fn console_getchar() -> usize {
 }
 
 /// use sbi call to shutdown the kernel
-pub fn shutdown() -> ! {
+///
+/// `failure` is reported as the reason through the System Reset extension,
+/// so that QEMU exits with a non-zero status. Firmware without it falls back
+/// to the legacy call, which cannot tell the two apart. If neither is there,
+/// the hart just waits forever, as panicking would only bring us back here.
+pub fn shutdown(failure: bool) -> ! {
+    let reason = if failure {
+        SRST_REASON_FAILURE
+    } else {
+        SRST_REASON_NONE
+    };
+    sbi_call_ext(SBI_EXT_SRST, SBI_SRST_RESET, SRST_TYPE_SHUTDOWN, reason);
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
-    panic!("It should shutdown!");
+    loop {
+        unsafe {
+            core::arch::asm!("wfi");
+        }
+    }
 }
@@ -0,0 +1,37 @@
+//! Queues of tasks blocked on something
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// Tasks waiting for some condition, woken up together once it may hold
+///
+/// Waiters must check the condition again after waking up, as another task
+/// may have got there first.
+pub struct WaitQueue {
+    queue: UPSafeCell<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl WaitQueue {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            queue: unsafe { UPSafeCell::new(VecDeque::new()) },
+        }
+    }
+    /// Block the current task until [`Self::wake_all`] is called
+    pub fn wait(&self) {
+        self.queue
+            .exclusive_access()
+            .push_back(current_task().unwrap());
+        block_current_and_run_next();
+    }
+    /// Wake up all waiting tasks
+    pub fn wake_all(&self) {
+        let tasks: VecDeque<_> = self.queue.exclusive_access().drain(..).collect();
+        for task in tasks {
+            wakeup_task(task);
+        }
+    }
+}
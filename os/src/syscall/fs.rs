@@ -1,18 +1,132 @@
 //! File and filesystem-related syscalls
+//!
+//! Files are reached through the fd table of the current task, see
+//! [`crate::fs::File`].
 
-const FD_STDOUT: usize = 1;
+use super::{read_user_str, UserBuffer, UserPtr, PATH_MAX};
+use crate::config::MAX_FD;
+use crate::error::{Errno, KernelResult};
+use crate::fs::{make_pipe, open_file, File, OpenFlags};
+use crate::task::current_task;
+use alloc::sync::Arc;
 
-// YOUR JOB: 修改 sys_write 使之通过测试
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let slice = unsafe { core::slice::from_raw_parts(buf, len) };
-            let str = core::str::from_utf8(slice).unwrap();
-            print!("{}", str);
-            len as isize
-        }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+/// Get the file open as `fd` in the current task
+fn get_file(fd: usize) -> KernelResult<Arc<dyn File>> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    inner
+        .fd_table
+        .get(fd)
+        .and_then(|file| file.clone())
+        .ok_or(Errno::EBADF)
+}
+
+/// Read at most `len` bytes into `buf` from a file with `fd`
+///
+/// Reading the console blocks until a whole line has been typed, and returns
+/// at most one line. A return value of 0 means end of file.
+pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> KernelResult<usize> {
+    let file = get_file(fd)?;
+    if !file.readable() {
+        return Err(Errno::EBADF);
+    }
+    // check the whole buffer before waiting for input
+    let buffer = UserBuffer::new(buf, len, true)?;
+    // the fd table is not borrowed here, so that reading may block
+    file.read(buffer)
+}
+
+/// write buf of length `len` to a file with `fd`
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> KernelResult<usize> {
+    let file = get_file(fd)?;
+    if !file.writable() {
+        return Err(Errno::EBADF);
+    }
+    file.write(UserBuffer::new(buf, len, false)?)
+}
+
+/// Open the file at `path` with [`OpenFlags`] `flags`, returning its fd
+///
+/// Fails with [`Errno::ENOENT`] if there is no such file and `CREATE` is not
+/// given, and with [`Errno::ENOSPC`] if it cannot be created for lack of room.
+pub fn sys_open(path: *const u8, flags: u32) -> KernelResult<usize> {
+    let path = read_user_str(path, PATH_MAX)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let inode = open_file(path.as_str(), flags)?;
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let fd = inner.alloc_fd()?;
+    inner.fd_table[fd] = Some(inode);
+    Ok(fd)
+}
+
+/// Close the file descriptor `fd`
+pub fn sys_close(fd: usize) -> KernelResult<usize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner
+        .fd_table
+        .get_mut(fd)
+        .and_then(Option::take)
+        .ok_or(Errno::EBADF)?;
+    Ok(0)
+}
+
+/// Duplicate `fd` into the lowest free file descriptor, returning it
+pub fn sys_dup(fd: usize) -> KernelResult<usize> {
+    let file = get_file(fd)?;
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let new_fd = inner.alloc_fd()?;
+    inner.fd_table[new_fd] = Some(file);
+    Ok(new_fd)
+}
+
+/// Duplicate `old_fd` into `new_fd`, closing whatever `new_fd` was, and
+/// return `new_fd`
+///
+/// `flags` must be 0, and the two must differ. This is how a parent rewires
+/// the stdin and stdout of a child between `fork` and `exec`.
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> KernelResult<usize> {
+    let file = get_file(old_fd)?;
+    if new_fd >= MAX_FD {
+        return Err(Errno::EBADF);
+    }
+    if old_fd == new_fd || flags != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if inner.fd_table.len() <= new_fd {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    inner.fd_table[new_fd] = Some(file);
+    Ok(new_fd)
+}
+
+/// Create a pipe, storing the fd of its read end in `pipe[0]` and that of its
+/// write end in `pipe[1]`
+pub fn sys_pipe(pipe: *mut usize) -> KernelResult<usize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let (pipe_read, pipe_write) = make_pipe();
+    let read_fd = inner.alloc_fd()?;
+    inner.fd_table[read_fd] = Some(pipe_read);
+    let write_fd = match inner.alloc_fd() {
+        Ok(fd) => fd,
+        Err(err) => {
+            inner.fd_table[read_fd] = None;
+            return Err(err);
         }
+    };
+    inner.fd_table[write_fd] = Some(pipe_write);
+    // the fd table must not be borrowed while accessing user memory
+    drop(inner);
+    if let Err(err) = UserPtr::new(pipe as *mut [usize; 2]).write([read_fd, write_fd]) {
+        let mut inner = task.inner_exclusive_access();
+        inner.fd_table[read_fd] = None;
+        inner.fd_table[write_fd] = None;
+        return Err(err);
     }
+    Ok(0)
 }
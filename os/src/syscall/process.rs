@@ -1,58 +1,304 @@
 //! Process management syscalls
 
-use crate::config::MAX_SYSCALL_NUM;
-use crate::task::{exit_current_and_run_next, suspend_current_and_run_next, TaskStatus, sys_call_stat};
-use crate::timer::get_time_us;
+use super::{read_user_str, user_ptr::USER_SPACE_TOP, UserPtr, PATH_MAX};
+use crate::config::{MAX_SYSCALL_NUM, MIN_PRIORITY, PAGE_SIZE, USER_HEAP_LIMIT};
+use crate::error::{Errno, KernelResult};
+use crate::fs::read_app;
+use crate::mm::{frame_stats, MapPermission, VirtAddr, VirtPageNum};
+use crate::task::{
+    add_task, block_current_and_run_next, current_task, exit_current_and_run_next,
+    set_current_priority, suspend_current_and_run_next, sys_call_stat, yield_current_and_run_next,
+    TaskControlBlock, TaskStatus,
+};
+use crate::timer::{add_timer, duration_to_cycles, get_time, get_time_us};
+use alloc::sync::Arc;
+
+/// option of `sys_waitpid`: do not block if no child has exited
+pub const WNOHANG: usize = 1;
+
+/// protection of `sys_mmap`: pages can be read
+pub const PROT_READ: usize = 1 << 0;
+/// protection of `sys_mmap`: pages can be written
+pub const PROT_WRITE: usize = 1 << 1;
+/// protection of `sys_mmap`: pages can be executed
+pub const PROT_EXEC: usize = 1 << 2;
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
 }
 
+/// `struct timespec` of Linux
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TimeSpec {
+    pub sec: isize,
+    pub nsec: isize,
+}
+
+#[derive(Copy, Clone)]
 pub struct TaskInfo {
-    status: TaskStatus,
-    syscall_times: [u32; MAX_SYSCALL_NUM],
-    time: usize,
+    pub status: TaskStatus,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    pub time: usize,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// physical memory usage of the whole system
+pub struct MemStat {
+    pub page_size: usize,
+    pub total_frames: usize,
+    pub free_frames: usize,
 }
 
 /// task exits and submit an exit code
 pub fn sys_exit(exit_code: i32) -> ! {
-    info!("[kernel] Application exited with code {}", exit_code);
-    exit_current_and_run_next();
+    info!(
+        "[kernel] Application {} exited with code {}",
+        current_task().unwrap().getpid(),
+        exit_code
+    );
+    exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
 /// current task gives up resources for other tasks
-pub fn sys_yield() -> isize {
-    suspend_current_and_run_next();
-    0
+pub fn sys_yield() -> KernelResult<usize> {
+    yield_current_and_run_next();
+    Ok(0)
+}
+
+/// Set the scheduling priority of the current task, returning it.
+///
+/// Priorities below `MIN_PRIORITY` are rejected with `EINVAL`. How the
+/// priority is used depends on the scheduler the kernel is built with.
+pub fn sys_set_priority(prio: isize) -> KernelResult<usize> {
+    if prio < MIN_PRIORITY as isize {
+        return Err(Errno::EINVAL);
+    }
+    set_current_priority(prio as usize);
+    Ok(prio as usize)
+}
+
+/// get the pid of the current task
+pub fn sys_getpid() -> KernelResult<usize> {
+    Ok(current_task().unwrap().getpid())
+}
+
+/// duplicate the current task, returning the pid of the child in the parent
+/// and 0 in the child
+///
+/// Fails with [`Errno::ENOMEM`] if there are not enough frames for the child.
+pub fn sys_fork() -> KernelResult<usize> {
+    let current_task = current_task().unwrap();
+    let new_task = current_task.fork().ok_or(Errno::ENOMEM)?;
+    let new_pid = new_task.getpid();
+    // we do not have to move to next instruction since we have done it before
+    // for child process, fork returns 0
+    let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
+    trap_cx.x[10] = 0;
+    // add new task to scheduler
+    add_task(new_task);
+    Ok(new_pid)
+}
+
+/// replace the program of the current task with the app named by `path`
+pub fn sys_exec(path: *const u8) -> KernelResult<usize> {
+    let path = read_user_str(path, PATH_MAX)?;
+    let data = read_app(path.as_str()).ok_or(Errno::ENOENT)?;
+    if let Err(err) = current_task().unwrap().exec(&path, &data) {
+        warn!("[kernel] Failed to exec {}: {}", path, err);
+        return Err(Errno::ENOEXEC);
+    }
+    Ok(0)
+}
+
+/// Wait for a child to exit, and store its exit code into `exit_code_ptr`
+/// unless it is null.
+///
+/// `pid == -1` means any child. Returns the pid of the reaped child, or fails
+/// with [`Errno::ECHILD`] if there is no such child. With [`WNOHANG`] in
+/// `options`, returns 0 at once if no such child has exited yet.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> KernelResult<usize> {
+    if options & !WNOHANG != 0 {
+        return Err(Errno::EINVAL);
+    }
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        let matches = |p: &Arc<TaskControlBlock>| pid == -1 || pid as usize == p.getpid();
+        if !inner.children.iter().any(matches) {
+            return Err(Errno::ECHILD);
+        }
+        let zombie = inner
+            .children
+            .iter()
+            .position(|p| matches(p) && p.inner_exclusive_access().is_zombie());
+        if let Some(idx) = zombie {
+            let child = inner.children.remove(idx);
+            let found_pid = child.getpid();
+            // a stray reference only delays freeing the child
+            if Arc::strong_count(&child) != 1 {
+                warn!("[kernel] pid {} is still referenced once reaped", found_pid);
+            }
+            let exit_code = child.inner_exclusive_access().exit_code;
+            // the TCB must not be borrowed while accessing user memory
+            drop(inner);
+            drop(task);
+            if !exit_code_ptr.is_null() {
+                UserPtr::new(exit_code_ptr).write(exit_code)?;
+            }
+            return Ok(found_pid);
+        }
+        if options & WNOHANG != 0 {
+            return Ok(0);
+        }
+        // let the children run until one of them exits
+        drop(inner);
+        drop(task);
+        suspend_current_and_run_next();
+    }
 }
 
 /// get time with second and microsecond
-pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
+pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> KernelResult<usize> {
     let us = get_time_us();
-    unsafe {
-        *ts = TimeVal {
-            sec: us / 1_000_000,
-            usec: us % 1_000_000,
-        };
+    let time = TimeVal {
+        sec: us / 1_000_000,
+        usec: us % 1_000_000,
+    };
+    UserPtr::new(ts).write(time)?;
+    Ok(0)
+}
+
+/// Sleep for the duration pointed by `req`.
+///
+/// Sleeps are never interrupted, so `rem` is never written to.
+pub fn sys_nanosleep(req: *const TimeSpec, _rem: *mut TimeSpec) -> KernelResult<usize> {
+    let req = UserPtr::new(req as *mut TimeSpec).read()?;
+    if req.sec < 0 || !(0..1_000_000_000).contains(&req.nsec) {
+        return Err(Errno::EINVAL);
     }
-    0
+    let expire = get_time().saturating_add(duration_to_cycles(req.sec as usize, req.nsec as usize));
+    add_timer(expire, current_task().unwrap());
+    block_current_and_run_next();
+    Ok(0)
 }
 
 /// fill the struct pointed by ti with task info
-pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
-    if ti.is_null() {
-        return -1;
-    }
+pub fn sys_task_info(ti: *mut TaskInfo) -> KernelResult<usize> {
     let stat = sys_call_stat();
-    // Unsafe indeed. Maybe we can do better after Ch. 4.
-    unsafe {
-        (*ti).status = TaskStatus::Running;
-        (*ti).syscall_times = stat.sys_call_stat;
-        (*ti).time = (get_time_us() - stat.first_run_time) / 1000;
+    let info = TaskInfo {
+        status: TaskStatus::Running,
+        syscall_times: stat.sys_call_stat,
+        time: (get_time_us() - stat.first_run_time) / 1000,
+    };
+    UserPtr::new(ti).write(info)?;
+    Ok(0)
+}
+
+/// fill the struct pointed by ms with physical memory usage
+pub fn sys_mem_stat(ms: *mut MemStat) -> KernelResult<usize> {
+    let stats = frame_stats();
+    let stat = MemStat {
+        page_size: PAGE_SIZE,
+        total_frames: stats.total,
+        free_frames: stats.free,
+    };
+    UserPtr::new(ms).write(stat)?;
+    Ok(0)
+}
+
+/// Check that `[start, start + len)` is a non-empty page-aligned range of
+/// user space, returning its pages
+fn user_page_range(start: usize, len: usize) -> KernelResult<(VirtPageNum, VirtPageNum)> {
+    let end = start.checked_add(len).ok_or(Errno::EINVAL)?;
+    if start % PAGE_SIZE != 0 || len == 0 || end > USER_SPACE_TOP {
+        return Err(Errno::EINVAL);
+    }
+    Ok((VirtAddr::from(start).floor(), VirtAddr::from(end).ceil()))
+}
+
+/// Whether there are enough free frames to map `pages` more pages
+fn enough_frames(pages: usize) -> bool {
+    // page tables may need a few more frames
+    pages + pages / 512 + 3 <= frame_stats().free
+}
+
+/// Map `len` bytes of zeroed memory at `start`, returning `start`
+///
+/// `start` must be page aligned, and `len` is rounded up to whole pages.
+/// `prot` is a non-empty combination of [`PROT_READ`], [`PROT_WRITE`] and
+/// [`PROT_EXEC`], which must allow reading if it allows writing, as the MMU
+/// has no write-only pages. Fails with [`Errno::EINVAL`] if any of the pages
+/// is already mapped.
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> KernelResult<usize> {
+    let (start_vpn, end_vpn) = user_page_range(start, len)?;
+    let write_only = prot & PROT_WRITE != 0 && prot & PROT_READ == 0;
+    if prot == 0 || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 || write_only {
+        return Err(Errno::EINVAL);
+    }
+    if !enough_frames(end_vpn.0 - start_vpn.0) {
+        return Err(Errno::ENOMEM);
+    }
+    let permission = MapPermission::from_bits((prot << 1) as u8).unwrap() | MapPermission::U;
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if !inner.memory_set.mmap(start_vpn, end_vpn, permission) {
+        return Err(Errno::EINVAL);
+    }
+    Ok(start)
+}
+
+/// Unmap `len` bytes at `start`, which must all have been mapped by
+/// [`sys_mmap`]
+pub fn sys_munmap(start: usize, len: usize) -> KernelResult<usize> {
+    let (start_vpn, end_vpn) = user_page_range(start, len)?;
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if !inner.memory_set.munmap(start_vpn, end_vpn) {
+        return Err(Errno::EINVAL);
+    }
+    Ok(0)
+}
+
+/// Move the program break of the current task by `increment` bytes, returning
+/// the old break
+///
+/// The heap is mapped or unmapped page by page as the break moves. Fails with
+/// [`Errno::ENOMEM`] if the heap would grow beyond [`USER_HEAP_LIMIT`] or into
+/// other mappings, and with [`Errno::EINVAL`] if it would shrink below its
+/// bottom.
+pub fn sys_sbrk(increment: isize) -> KernelResult<usize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let heap_bottom = inner.heap_bottom;
+    let old_brk = inner.program_brk;
+    let new_brk = if increment < 0 {
+        match old_brk.checked_sub(increment.unsigned_abs()) {
+            Some(brk) if brk >= heap_bottom => brk,
+            _ => return Err(Errno::EINVAL),
+        }
+    } else {
+        match old_brk.checked_add(increment as usize) {
+            Some(brk) if brk - heap_bottom <= USER_HEAP_LIMIT => brk,
+            _ => return Err(Errno::ENOMEM),
+        }
+    };
+    let memory_set = &mut inner.memory_set;
+    if new_brk < old_brk {
+        if !memory_set.shrink_to(heap_bottom.into(), new_brk.into()) {
+            return Err(Errno::EINVAL);
+        }
+    } else {
+        let pages = VirtAddr::from(new_brk).ceil().0 - VirtAddr::from(old_brk).ceil().0;
+        if !enough_frames(pages) || !memory_set.append_to(heap_bottom.into(), new_brk.into()) {
+            return Err(Errno::ENOMEM);
+        }
     }
-    0
+    inner.program_brk = new_brk;
+    Ok(old_brk)
 }
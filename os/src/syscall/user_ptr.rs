@@ -0,0 +1,153 @@
+//! Checked access to user memory
+//!
+//! Syscall arguments that point into user space must never be dereferenced
+//! directly: the kernel runs in its own address space, and the pointer may
+//! well be bogus. Instead, [`UserBuffer`] walks the page table of the calling
+//! task page by page, making sure every page is mapped, accessible from user
+//! mode, and has the permissions the access needs.
+
+use crate::config::PAGE_SIZE;
+use crate::error::{Errno, KernelResult};
+use crate::mm::{PageTable, StepByOne, VirtAddr};
+use crate::task::current_user_token;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// User space is the lower half of the Sv39 address space
+pub const USER_SPACE_TOP: usize = 1 << 38;
+
+/// A range of user memory, translated into kernel-accessible slices
+pub struct UserBuffer {
+    pub buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    /// Translate `[ptr, ptr + len)` in the address space of the current task.
+    ///
+    /// Fails with [`Errno::EFAULT`] if any byte of the range is not readable
+    /// from user mode, or not writable when `writable` is set.
+    pub fn new(ptr: *const u8, len: usize, writable: bool) -> KernelResult<Self> {
+        let mut buffers = Vec::new();
+        if len == 0 {
+            return Ok(Self { buffers });
+        }
+        let mut start = ptr as usize;
+        let end = start.checked_add(len).ok_or(Errno::EFAULT)?;
+        if start == 0 || end > USER_SPACE_TOP {
+            return Err(Errno::EFAULT);
+        }
+        let page_table = PageTable::from_token(current_user_token());
+        while start < end {
+            let start_va = VirtAddr::from(start);
+            let mut vpn = start_va.floor();
+            let pte = page_table.translate(vpn).ok_or(Errno::EFAULT)?;
+            if !pte.is_valid() || !pte.is_user() || !pte.readable() || (writable && !pte.writable())
+            {
+                return Err(Errno::EFAULT);
+            }
+            let ppn = pte.ppn();
+            vpn.step();
+            let mut end_va: VirtAddr = vpn.into();
+            end_va = end_va.min(VirtAddr::from(end));
+            if end_va.page_offset() == 0 {
+                buffers.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+            } else {
+                buffers.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+            }
+            start = end_va.into();
+        }
+        Ok(Self { buffers })
+    }
+    /// Total length in bytes
+    pub fn len(&self) -> usize {
+        self.buffers.iter().map(|b| b.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+    /// Copy the whole range into a kernel buffer
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(self.len());
+        for buffer in self.buffers.iter() {
+            v.extend_from_slice(buffer);
+        }
+        v
+    }
+    /// Copy `data` to the start of the range, returning the number of bytes
+    /// copied, which is short if the range is
+    pub fn fill(&mut self, data: &[u8]) -> usize {
+        let mut start = 0;
+        for buffer in self.buffers.iter_mut() {
+            let len = buffer.len().min(data.len() - start);
+            buffer[..len].copy_from_slice(&data[start..start + len]);
+            start += len;
+        }
+        start
+    }
+    /// Fill the range with `data`, which must be exactly as long
+    pub fn copy_from_slice(&mut self, data: &[u8]) {
+        assert_eq!(self.len(), data.len());
+        let mut start = 0;
+        for buffer in self.buffers.iter_mut() {
+            let end = start + buffer.len();
+            buffer.copy_from_slice(&data[start..end]);
+            start = end;
+        }
+    }
+}
+
+/// A pointer to a `T` in user space
+///
+/// Values are copied byte by byte, so they may be unaligned and may cross
+/// page boundaries.
+pub struct UserPtr<T> {
+    ptr: *mut T,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(ptr: *mut T) -> Self {
+        Self { ptr }
+    }
+    /// Load a value from user space
+    ///
+    /// `T` must be valid for any bit pattern, as user space can put anything
+    /// there.
+    pub fn read(&self) -> KernelResult<T> {
+        let buffer = UserBuffer::new(self.ptr as *const u8, size_of::<T>(), false)?;
+        let bytes = buffer.to_vec();
+        Ok(unsafe { (bytes.as_ptr() as *const T).read_unaligned() })
+    }
+    /// Store `value` into user space
+    pub fn write(&self, value: T) -> KernelResult<()> {
+        let mut buffer = UserBuffer::new(self.ptr as *const u8, size_of::<T>(), true)?;
+        let bytes =
+            unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Read a nul-terminated string of at most `max_len` bytes from user space.
+///
+/// Fails with [`Errno::ENAMETOOLONG`] if no nul byte shows up in time, and
+/// with [`Errno::EINVAL`] if the string is not valid UTF-8.
+pub fn read_user_str(ptr: *const u8, max_len: usize) -> KernelResult<String> {
+    let mut bytes = Vec::new();
+    let mut start = ptr as usize;
+    while bytes.len() < max_len {
+        // read up to the end of the page, so that we never touch the next
+        // page unless the string goes on
+        let len = (PAGE_SIZE - start % PAGE_SIZE).min(max_len - bytes.len());
+        let buffer = UserBuffer::new(start as *const u8, len, false)?;
+        for slice in buffer.buffers.iter() {
+            if let Some(end) = slice.iter().position(|c| *c == b'\0') {
+                bytes.extend_from_slice(&slice[..end]);
+                return String::from_utf8(bytes).map_err(|_| Errno::EINVAL);
+            }
+            bytes.extend_from_slice(slice);
+        }
+        start += len;
+    }
+    Err(Errno::ENAMETOOLONG)
+}
@@ -1,5 +1,7 @@
 //! Implementation of [`TaskContext`]
 
+use crate::trap::trap_return;
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 /// task context structure containing some registers
@@ -17,12 +19,10 @@ impl TaskContext {
             s: [0; 12],
         }
     }
-    pub fn goto_restore(kstack_ptr: usize) -> Self {
-        extern "C" {
-            fn __restore();
-        }
+    /// Context that starts executing `trap_return` on the given kernel stack
+    pub fn goto_trap_return(kstack_ptr: usize) -> Self {
         Self {
-            ra: __restore as usize,
+            ra: trap_return as usize,
             sp: kstack_ptr,
             s: [0; 12],
         }
@@ -0,0 +1,61 @@
+//! Implementation of [`TaskManager`]
+//!
+//! It is only used to manage processes and schedule process based on ready
+//! queue. Other CPU process monitoring functions are in Processor. The
+//! scheduling policy itself is a [`Scheduler`] picked at compile time.
+
+use super::scheduler::{Scheduler, SchedulerImpl};
+use super::TaskControlBlock;
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// The set of tasks ready to run, ordered by the scheduler `S`
+pub struct TaskManager<S: Scheduler> {
+    scheduler: S,
+}
+
+impl<S: Scheduler> TaskManager<S> {
+    fn new() -> Self {
+        Self {
+            scheduler: S::new(),
+        }
+    }
+    /// Make a task ready to run
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.scheduler.add(task);
+    }
+    /// Take the task that should run next
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.scheduler.pick_next()
+    }
+    /// Account a timer tick to the running task, returning whether it should
+    /// give up the CPU
+    pub fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        self.scheduler.on_tick(current)
+    }
+    /// Note that the running task gives up the CPU by itself
+    pub fn yield_(&mut self, current: &Arc<TaskControlBlock>) {
+        self.scheduler.on_yield(current);
+    }
+    /// Take a task out of the ready set, returning whether it was there
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.scheduler.remove(task)
+    }
+}
+
+lazy_static! {
+    /// TASK_MANAGER instance through lazy_static!
+    pub static ref TASK_MANAGER: UPSafeCell<TaskManager<SchedulerImpl>> =
+        unsafe { UPSafeCell::new(TaskManager::new()) };
+}
+
+/// Add a task to the ready queue
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.exclusive_access().add(task);
+}
+
+/// Take a task out of the ready queue
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.exclusive_access().fetch()
+}
@@ -4,202 +4,218 @@
 //! implemented here.
 //!
 //! A single global instance of [`TaskManager`] called `TASK_MANAGER` controls
-//! all the tasks in the operating system.
+//! all the tasks ready to run in the operating system, while [`Processor`]
+//! keeps track of the task currently running and the idle control flow that
+//! picks the next one in [`run_tasks()`].
+//!
+//! Tasks form a tree: each task but the init process ([`INITPROC`]) is
+//! created by `fork`ing its parent. An exited task stays around as a zombie
+//! until its parent collects its exit code with `waitpid`, and its children
+//! are handed over to the init process. Tasks live on the heap, and their
+//! pids and kernel stacks are recycled once they are reaped.
 //!
 //! Be careful when you see [`__switch`]. Control flow around this function
 //! might not be what you expect.
 
 mod context;
+mod manager;
+mod pid;
+mod processor;
+mod report;
+mod scheduler;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
-use crate::config::{MAX_APP_NUM, MAX_SYSCALL_NUM};
-use crate::loader::{get_num_app, init_app_cx};
-use crate::sync::UPSafeCell;
-use crate::timer::get_time_us;
+use crate::config::{INITPROC_NAME, MAX_SYSCALL_NUM};
+use crate::fs::read_app;
+use crate::mm::frame_stats;
+use alloc::sync::Arc;
 use lazy_static::*;
+use report::record_exit;
+pub use manager::{add_task, fetch_task, TaskManager, TASK_MANAGER};
+pub use pid::{pid_alloc, KernelStack, PidHandle};
+pub use processor::{
+    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    Processor,
+};
+pub use report::print_exit_summary;
+pub use scheduler::{SchedEntity, Scheduler, SchedulerImpl};
 pub use switch::__switch;
-pub use task::{TaskControlBlock, TaskStatus, TaskStatistics};
+pub use task::{TaskControlBlock, TaskControlBlockInner, TaskStatistics, TaskStatus};
 
 pub use context::TaskContext;
 
-/// The task manager, where all the tasks are managed.
-///
-/// Functions implemented on `TaskManager` deals with all task state transitions
-/// and task context switching. For convenience, you can find wrappers around it
-/// in the module level.
-///
-/// Most of `TaskManager` are hidden behind the field `inner`, to defer
-/// borrowing checks to runtime. You can see examples on how to use `inner` in
-/// existing functions on `TaskManager`.
-pub struct TaskManager {
-    /// total number of tasks
-    num_app: usize,
-    /// use inner value to get mutable access
-    inner: UPSafeCell<TaskManagerInner>,
-}
-
-/// The task manager inner in 'UPSafeCell'
-struct TaskManagerInner {
-    /// task list
-    tasks: [TaskControlBlock; MAX_APP_NUM],
-    /// id of current `Running` task
-    current_task: usize,
-}
-
 lazy_static! {
-    /// a `TaskManager` instance through lazy_static!
-    pub static ref TASK_MANAGER: TaskManager = {
-        let num_app = get_num_app();
-        let mut tasks = [TaskControlBlock {
-            task_cx: TaskContext::zero_init(),
-            task_status: TaskStatus::UnInit,
-            task_statistics: TaskStatistics::zero_init(),
-        }; MAX_APP_NUM];
-        for (i, t) in tasks.iter_mut().enumerate().take(num_app) {
-            t.task_cx = TaskContext::goto_restore(init_app_cx(i));
-            t.task_status = TaskStatus::Ready;
-        }
-        TaskManager {
-            num_app,
-            inner: unsafe {
-                UPSafeCell::new(TaskManagerInner {
-                    tasks,
-                    current_task: 0,
-                })
-            },
-        }
+    /// the init process, which adopts all orphaned tasks
+    pub static ref INITPROC: Arc<TaskControlBlock> = {
+        let elf_data = read_app(INITPROC_NAME).expect("init process not found");
+        Arc::new(TaskControlBlock::new(INITPROC_NAME, &elf_data).unwrap())
     };
 }
 
-impl TaskManager {
-    /// Run the first task in task list.
-    ///
-    /// Generally, the first task in task list is an idle task (we call it zero process later).
-    /// But in ch3, we load apps statically, so the first task is a real app.
-    fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let task0 = &mut inner.tasks[0];
-        task0.task_statistics.first_run_time = get_time_us();
-        task0.task_status = TaskStatus::Running;
-        let next_task_cx_ptr = &task0.task_cx as *const TaskContext;
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        // before this, we should drop local variables that must be dropped manually
-        unsafe {
-            __switch(&mut _unused as *mut TaskContext, next_task_cx_ptr);
-        }
-        panic!("unreachable in run_first_task!");
-    }
-
-    /// Change the status of current `Running` task into `Ready`.
-    fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Ready;
-    }
+/// Add the init process to the ready queue
+pub fn add_initproc() {
+    add_task(INITPROC.clone());
+}
 
-    /// Change the status of current `Running` task into `Exited`.
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Exited;
-    }
+/// Suspend the current 'Running' task and run the next task in task list.
+pub fn suspend_current_and_run_next() {
+    // There must be an application running.
+    let task = take_current_task().unwrap();
+
+    // ---- access current TCB exclusively
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    // Change status to Ready
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    // ---- release current TCB
+
+    // push back to ready queue.
+    add_task(task);
+    // jump to scheduling cycle
+    schedule(task_cx_ptr);
+}
 
-    /// Find next task to run and return task id.
-    ///
-    /// In this case, we only return the first `Ready` task in task list.
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        (current + 1..current + self.num_app + 1)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
-    }
+/// Block the current 'Running' task and run the next task in task list.
+///
+/// The task is not put back into the ready queue, so whoever it waits for
+/// must hold on to it and call [`wakeup_task`] later.
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    drop(task_inner);
+    drop(task);
+    schedule(task_cx_ptr);
+}
 
-    /// Switch current `Running` task to the task we have found,
-    /// or there is no `Ready` task and we can exit with all applications completed
-    fn run_next_task(&self) {
-        if let Some(next) = self.find_next_task() {
-            let mut inner = self.inner.exclusive_access();
-            let current = inner.current_task;
-            if inner.tasks[next].task_statistics.first_run_time == 0 {
-                inner.tasks[next].task_statistics.first_run_time = get_time_us();
-            }
-            inner.tasks[next].task_status = TaskStatus::Running;
-            inner.current_task = next;
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
-            drop(inner);
-            // before this, we should drop local variables that must be dropped manually
-            unsafe {
-                __switch(current_task_cx_ptr, next_task_cx_ptr);
-            }
-            // go back to user mode
-        } else {
-            panic!("All applications completed!");
-        }
-    }
+/// Make a 'Blocked' task ready to run again
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    task.inner_exclusive_access().task_status = TaskStatus::Ready;
+    add_task(task);
+}
 
-    /// Return the stats for the current task
-    /// 
-    /// It does a somehow costly copy for each call for now.
-    fn sys_call_stat(&self) -> TaskStatistics {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].task_statistics.clone()
-    }
+/// Give up the CPU voluntarily, letting the scheduler know about it
+pub fn yield_current_and_run_next() {
+    let task = current_task().unwrap();
+    TASK_MANAGER.exclusive_access().yield_(&task);
+    drop(task);
+    suspend_current_and_run_next();
+}
 
-    /// Update the sys call stat
-    fn update_sys_call_stat(&self, sys_call: usize) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_statistics.sys_call_stat[sys_call] += 1;
+/// Handle a timer tick for the current 'Running' task.
+///
+/// The scheduler decides whether the task runs on or gets preempted.
+pub fn tick_current_task() {
+    let task = current_task().unwrap();
+    let preempt = TASK_MANAGER.exclusive_access().tick(&task);
+    drop(task);
+    if preempt {
+        suspend_current_and_run_next();
     }
-
-    // LAB1: Try to implement your function to update or get task info!
 }
 
-/// Run the first task in task list.
-pub fn run_first_task() {
-    TASK_MANAGER.run_first_task();
+/// Exit the current 'Running' task and run the next task in task list.
+///
+/// The task becomes a zombie with `exit_code`, gives back the memory it used,
+/// and hands its children over to the init process. The init process may exit
+/// too, keeping its children, and the kernel shuts down once they are all
+/// gone, see [`all_tasks_exited`].
+pub fn exit_current_and_run_next(exit_code: i32) {
+    do_exit(exit_code, false);
 }
 
-/// Switch current `Running` task to the task we have found,
-/// or there is no `Ready` task and we can exit with all applications completed
-fn run_next_task() {
-    TASK_MANAGER.run_next_task();
+/// Kill the current 'Running' task after a fatal exception, and run the next
+/// task in task list.
+///
+/// This is the same as [`exit_current_and_run_next`], except that the task
+/// counts as crashed when the kernel shuts down.
+pub fn kill_current_and_run_next(exit_code: i32) {
+    do_exit(exit_code, true);
 }
 
-/// Change the status of current `Running` task into `Ready`.
-fn mark_current_suspended() {
-    TASK_MANAGER.mark_current_suspended();
-}
+fn do_exit(exit_code: i32, crashed: bool) {
+    // take from Processor
+    let task = take_current_task().unwrap();
+    let is_initproc = Arc::ptr_eq(&task, &INITPROC);
+    if is_initproc {
+        info!("[kernel] init process exited with code {}", exit_code);
+    }
+    // **** access current TCB exclusively
+    let mut inner = task.inner_exclusive_access();
+    // Change status to Zombie
+    inner.task_status = TaskStatus::Zombie;
+    // Record exit code
+    inner.exit_code = exit_code;
+    record_exit(task.getpid(), inner.name.clone(), exit_code, crashed);
+    // do not move to its parent but under initproc
+
+    // ++++++ access initproc TCB exclusively
+    if !is_initproc {
+        let mut initproc_inner = INITPROC.inner_exclusive_access();
+        for child in inner.children.iter() {
+            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+            initproc_inner.children.push(child.clone());
+        }
+    }
+    // ++++++ release parent PCB
 
-/// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
+    if !is_initproc {
+        inner.children.clear();
+    }
+    // deallocate user space
+    inner.memory_set.recycle_data_pages();
+    // close all files
+    inner.fd_table.clear();
+    drop(inner);
+    // **** release current PCB
+    let stats = frame_stats();
+    debug!(
+        "[kernel] task {} exited, {}/{} frames in use",
+        task.getpid(),
+        stats.used(),
+        stats.total
+    );
+    // drop task manually to maintain rc correctly
+    drop(task);
+    // we do not have to save task context
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
 }
 
-/// Suspend the current 'Running' task and run the next task in task list.
-pub fn suspend_current_and_run_next() {
-    mark_current_suspended();
-    run_next_task();
+/// Set the scheduling priority of the current task
+pub fn set_current_priority(priority: usize) {
+    current_task().unwrap().inner_exclusive_access().sched.priority = priority;
 }
 
-/// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
-    run_next_task();
+/// Whether every task has exited
+///
+/// Exited tasks hand their children over to the init process, so a task is
+/// alive only if the init process or one of its children is.
+pub fn all_tasks_exited() -> bool {
+    let inner = INITPROC.inner_exclusive_access();
+    inner.is_zombie()
+        && inner
+            .children
+            .iter()
+            .all(|child| child.inner_exclusive_access().is_zombie())
 }
 
 /// Return the stats for the current task
 pub fn sys_call_stat() -> TaskStatistics {
-    TASK_MANAGER.sys_call_stat()
+    current_task().unwrap().inner_exclusive_access().task_statistics
 }
 
 /// Update the sys call stat for the current task
+///
+/// Syscall ids out of the range we keep statistics for are ignored.
 pub fn update_sys_call_stat(sys_call: usize) {
-    TASK_MANAGER.update_sys_call_stat(sys_call);
+    if sys_call >= MAX_SYSCALL_NUM {
+        return;
+    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().task_statistics.sys_call_stat[sys_call] += 1;
 }
-
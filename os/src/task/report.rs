@@ -0,0 +1,86 @@
+//! Exit codes of tasks, reported when the kernel shuts down
+//!
+//! Only the latest [`MAX_EXIT_RECORDS`] exits are remembered, so that a
+//! long-lived init process does not make the records grow without bounds.
+//! Every exit is counted, and any crash makes the shutdown a failure, whether
+//! its record is still there or not.
+
+use crate::config::MAX_EXIT_RECORDS;
+use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use lazy_static::*;
+
+/// how a task ended
+struct ExitRecord {
+    pid: usize,
+    name: String,
+    exit_code: i32,
+    /// killed by the kernel rather than exiting by itself
+    crashed: bool,
+}
+
+struct ExitReport {
+    /// the latest exits, oldest first
+    records: VecDeque<ExitRecord>,
+    /// number of tasks that have exited
+    exited: usize,
+    /// number of tasks that have crashed
+    crashed: usize,
+}
+
+lazy_static! {
+    static ref EXIT_REPORT: UPSafeCell<ExitReport> = unsafe {
+        UPSafeCell::new(ExitReport {
+            records: VecDeque::new(),
+            exited: 0,
+            crashed: 0,
+        })
+    };
+}
+
+/// Remember how a task ended, for [`print_exit_summary`]
+pub fn record_exit(pid: usize, name: String, exit_code: i32, crashed: bool) {
+    let mut report = EXIT_REPORT.exclusive_access();
+    report.exited += 1;
+    if crashed {
+        report.crashed += 1;
+    }
+    if report.records.len() == MAX_EXIT_RECORDS {
+        report.records.pop_front();
+    }
+    report.records.push_back(ExitRecord {
+        pid,
+        name,
+        exit_code,
+        crashed,
+    });
+}
+
+/// Print the exit codes of the latest tasks, returning whether any task
+/// crashed
+pub fn print_exit_summary() -> bool {
+    let report = EXIT_REPORT.exclusive_access();
+    println!("[kernel] {:>5} {:>9}  name", "pid", "exit code");
+    let dropped = report.exited - report.records.len();
+    if dropped > 0 {
+        println!(
+            "[kernel] {:>5} {:>9}  ({} earlier tasks)",
+            "...", "...", dropped
+        );
+    }
+    for record in report.records.iter() {
+        println!(
+            "[kernel] {:>5} {:>9}  {}{}",
+            record.pid,
+            record.exit_code,
+            record.name,
+            if record.crashed { " (crashed)" } else { "" }
+        );
+    }
+    println!(
+        "[kernel] {} tasks exited, {} crashed",
+        report.exited, report.crashed
+    );
+    report.crashed != 0
+}
@@ -0,0 +1,100 @@
+//! Multi-level feedback queue scheduling
+//!
+//! Tasks start at the highest level, which has the shortest time slice. A
+//! task that uses up its whole slice is moved one level down, while one that
+//! yields before its slice runs out is moved one level up, so that CPU hogs
+//! sink and interactive tasks stay responsive. Every `MLFQ_BOOST_INTERVAL`
+//! ticks all tasks go back to the highest level, so that none starves.
+
+use super::Scheduler;
+use crate::config::{MLFQ_BOOST_INTERVAL, MLFQ_QUANTA};
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+const LEVELS: usize = MLFQ_QUANTA.len();
+
+/// One FIFO queue per level, level 0 being the most important
+pub struct MlfqScheduler {
+    queues: [VecDeque<Arc<TaskControlBlock>>; LEVELS],
+    /// ticks since the last boost
+    ticks: usize,
+    /// number of boosts so far, to catch up tasks that were not ready then
+    epoch: usize,
+}
+
+impl MlfqScheduler {
+    /// Move every task back to the highest level
+    fn boost(&mut self, current: &Arc<TaskControlBlock>) {
+        self.ticks = 0;
+        self.epoch += 1;
+        for level in 1..LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                self.queues[0].push_back(task);
+            }
+        }
+        for task in self.queues[0].iter().chain(Some(current)) {
+            let mut inner = task.inner_exclusive_access();
+            inner.sched.level = 0;
+            inner.sched.ticks = 0;
+            inner.sched.epoch = self.epoch;
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            ticks: 0,
+            epoch: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        if inner.sched.epoch != self.epoch {
+            // missed a boost while it was not ready
+            inner.sched.level = 0;
+            inner.sched.ticks = 0;
+            inner.sched.epoch = self.epoch;
+        }
+        let level = inner.sched.level;
+        drop(inner);
+        self.queues[level].push_back(task);
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        self.ticks += 1;
+        if self.ticks >= MLFQ_BOOST_INTERVAL {
+            self.boost(current);
+            return true;
+        }
+        let mut inner = current.inner_exclusive_access();
+        inner.sched.ticks += 1;
+        if inner.sched.ticks >= MLFQ_QUANTA[inner.sched.level] {
+            // used up its slice
+            inner.sched.ticks = 0;
+            inner.sched.level = (inner.sched.level + 1).min(LEVELS - 1);
+            return true;
+        }
+        // a task at a higher level became ready
+        let level = inner.sched.level;
+        self.queues[..level].iter().any(|queue| !queue.is_empty())
+    }
+    fn on_yield(&mut self, current: &Arc<TaskControlBlock>) {
+        let mut inner = current.inner_exclusive_access();
+        inner.sched.ticks = 0;
+        inner.sched.level = inner.sched.level.saturating_sub(1);
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        for queue in self.queues.iter_mut() {
+            if let Some(idx) = queue.iter().position(|t| Arc::ptr_eq(t, task)) {
+                queue.remove(idx);
+                return true;
+            }
+        }
+        false
+    }
+}
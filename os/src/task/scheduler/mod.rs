@@ -0,0 +1,115 @@
+//! Scheduling policies
+//!
+//! A [`Scheduler`] keeps the tasks that are ready to run and decides which
+//! one goes next. Exactly one policy is compiled in, selected by cargo
+//! feature:
+//!
+//! - `sched-stride`: [`StrideScheduler`], proportional share by priority
+//! - `sched-priority`: [`PriorityScheduler`], fixed-priority preemptive
+//! - `sched-mlfq`: [`MlfqScheduler`], multi-level feedback queue
+//! - otherwise: [`RoundRobinScheduler`]
+//!
+//! Policies that need per-task state keep it in the [`SchedEntity`] of each
+//! task.
+
+#[cfg(feature = "sched-mlfq")]
+mod mlfq;
+#[cfg(feature = "sched-priority")]
+mod priority;
+#[cfg(not(any(
+    feature = "sched-stride",
+    feature = "sched-priority",
+    feature = "sched-mlfq"
+)))]
+mod round_robin;
+#[cfg(feature = "sched-stride")]
+mod stride;
+
+#[cfg(any(
+    all(feature = "sched-stride", feature = "sched-priority"),
+    all(feature = "sched-stride", feature = "sched-mlfq"),
+    all(feature = "sched-priority", feature = "sched-mlfq"),
+))]
+compile_error!("only one scheduler feature can be enabled at a time");
+
+use super::TaskControlBlock;
+use crate::config::DEFAULT_PRIORITY;
+use alloc::sync::Arc;
+
+#[cfg(feature = "sched-mlfq")]
+pub use mlfq::MlfqScheduler;
+#[cfg(feature = "sched-priority")]
+pub use priority::PriorityScheduler;
+#[cfg(not(any(
+    feature = "sched-stride",
+    feature = "sched-priority",
+    feature = "sched-mlfq"
+)))]
+pub use round_robin::RoundRobinScheduler;
+#[cfg(feature = "sched-stride")]
+pub use stride::StrideScheduler;
+
+/// the scheduler selected at compile time
+#[cfg(feature = "sched-mlfq")]
+pub type SchedulerImpl = MlfqScheduler;
+/// the scheduler selected at compile time
+#[cfg(feature = "sched-priority")]
+pub type SchedulerImpl = PriorityScheduler;
+/// the scheduler selected at compile time
+#[cfg(not(any(
+    feature = "sched-stride",
+    feature = "sched-priority",
+    feature = "sched-mlfq"
+)))]
+pub type SchedulerImpl = RoundRobinScheduler;
+/// the scheduler selected at compile time
+#[cfg(feature = "sched-stride")]
+pub type SchedulerImpl = StrideScheduler;
+
+/// A scheduling policy over the tasks that are ready to run
+///
+/// The running task is never held by the scheduler: it is taken out by
+/// [`pick_next`](Scheduler::pick_next) and comes back through
+/// [`add`](Scheduler::add) once it is ready again.
+pub trait Scheduler {
+    fn new() -> Self;
+    /// Make `task` ready to run
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// Take the task that should run next out of the ready set
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// Account a timer tick to the running task `current`, and tell whether it
+    /// should be preempted
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool;
+    /// Note that the running task `current` gives up the CPU by itself
+    fn on_yield(&mut self, _current: &Arc<TaskControlBlock>) {}
+    /// Take `task` out of the ready set if it is there
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool;
+}
+
+#[derive(Copy, Clone)]
+/// per-task scheduling state
+pub struct SchedEntity {
+    /// larger means more important; see `sys_set_priority`
+    pub priority: usize,
+    /// stride scheduling: virtual time consumed so far
+    pub pass: u64,
+    /// MLFQ: current level, 0 being the highest
+    pub level: usize,
+    /// MLFQ: ticks used of the time slice at the current level
+    pub ticks: usize,
+    /// MLFQ: the last boost this task took part in
+    pub epoch: usize,
+}
+
+impl SchedEntity {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            priority: DEFAULT_PRIORITY,
+            pass: 0,
+            level: 0,
+            ticks: 0,
+            epoch: 0,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Fixed-priority preemptive scheduling
+//!
+//! The ready task with the highest priority always runs, and tasks of the
+//! same priority take turns. Preemption is checked on timer ticks, so a more
+//! important task that becomes ready waits for at most one tick.
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+
+/// One FIFO queue per priority level
+pub struct PriorityScheduler {
+    queues: BTreeMap<usize, VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl Scheduler for PriorityScheduler {
+    fn new() -> Self {
+        Self {
+            queues: BTreeMap::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let priority = task.inner_exclusive_access().sched.priority;
+        self.queues.entry(priority).or_default().push_back(task);
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let (&priority, queue) = self.queues.iter_mut().next_back()?;
+        let task = queue.pop_front();
+        if queue.is_empty() {
+            self.queues.remove(&priority);
+        }
+        task
+    }
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        // give way to tasks at least as important as the current one
+        let priority = current.inner_exclusive_access().sched.priority;
+        self.queues
+            .keys()
+            .next_back()
+            .map_or(false, |&highest| highest >= priority)
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let priority = task.inner_exclusive_access().sched.priority;
+        let queue = match self.queues.get_mut(&priority) {
+            Some(queue) => queue,
+            None => return false,
+        };
+        let idx = match queue.iter().position(|t| Arc::ptr_eq(t, task)) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        queue.remove(idx);
+        if queue.is_empty() {
+            self.queues.remove(&priority);
+        }
+        true
+    }
+}
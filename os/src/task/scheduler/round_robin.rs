@@ -0,0 +1,36 @@
+//! Round-robin scheduling
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// A FIFO ready queue, where the running task is preempted on every tick
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn on_tick(&mut self, _current: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        if let Some(idx) = self.ready_queue.iter().position(|t| Arc::ptr_eq(t, task)) {
+            self.ready_queue.remove(idx);
+            true
+        } else {
+            false
+        }
+    }
+}
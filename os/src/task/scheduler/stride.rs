@@ -0,0 +1,64 @@
+//! Stride scheduling
+//!
+//! Each task advances its pass by `BIG_STRIDE / priority` every time it is
+//! picked, and the task with the smallest pass runs next, so that the share
+//! of CPU time a task gets is proportional to its priority.
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const BIG_STRIDE: u64 = 1 << 32;
+
+/// Compare passes, allowing them to wrap around.
+///
+/// As long as no priority is below 2, passes of ready tasks are never more
+/// than `BIG_STRIDE / 2` apart, so the wrapping difference tells their order.
+fn pass_before(a: u64, b: u64) -> bool {
+    (a.wrapping_sub(b) as i64) < 0
+}
+
+/// Ready tasks are kept unsorted, and the one with the smallest pass is
+/// searched for when picking.
+pub struct StrideScheduler {
+    ready: Vec<Arc<TaskControlBlock>>,
+}
+
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self { ready: Vec::new() }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready.push(task);
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let mut best: Option<(usize, u64)> = None;
+        for (idx, task) in self.ready.iter().enumerate() {
+            let pass = task.inner_exclusive_access().sched.pass;
+            if best.map_or(true, |(_, best_pass)| pass_before(pass, best_pass)) {
+                best = Some((idx, pass));
+            }
+        }
+        let (idx, _) = best?;
+        let task = self.ready.swap_remove(idx);
+        let mut inner = task.inner_exclusive_access();
+        inner.sched.pass = inner
+            .sched
+            .pass
+            .wrapping_add(BIG_STRIDE / inner.sched.priority as u64);
+        drop(inner);
+        Some(task)
+    }
+    fn on_tick(&mut self, _current: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        if let Some(idx) = self.ready.iter().position(|t| Arc::ptr_eq(t, task)) {
+            self.ready.swap_remove(idx);
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -1,7 +1,18 @@
 //! Types related to task management
 
-use super::TaskContext;
+use super::{pid_alloc, KernelStack, PidHandle, SchedEntity, TaskContext};
+use crate::config::{MAX_FD, TRAP_CONTEXT};
+use crate::error::{Errno, KernelResult};
+use crate::fs::{File, Stdin, Stdout};
+use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
 use crate::task::MAX_SYSCALL_NUM;
+use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
 
 #[derive(Copy, Clone)]
 /// task stats
@@ -16,19 +27,212 @@ impl TaskStatistics {
     }
 }
 
-#[derive(Copy, Clone)]
 /// task control block structure
+///
+/// Fields that never change after creation live directly in the block, while
+/// the rest is behind `inner`.
 pub struct TaskControlBlock {
+    pub pid: PidHandle,
+    pub kernel_stack: KernelStack,
+    inner: UPSafeCell<TaskControlBlockInner>,
+}
+
+/// mutable part of [`TaskControlBlock`]
+pub struct TaskControlBlockInner {
+    /// name of the app the task runs, for diagnostics
+    pub name: String,
     pub task_status: TaskStatus,
     pub task_cx: TaskContext,
     pub task_statistics: TaskStatistics,
+    /// scheduling state, see [`crate::task::Scheduler`]
+    pub sched: SchedEntity,
+    /// address space of the task
+    pub memory_set: MemorySet,
+    /// physical page holding the `TrapContext`
+    pub trap_cx_ppn: PhysPageNum,
+    /// size of the user memory, up to the top of the user stack
+    pub base_size: usize,
+    /// start of the heap, right above the user stack
+    pub heap_bottom: usize,
+    /// current end of the heap, moved by `sys_sbrk`
+    pub program_brk: usize,
+    pub parent: Option<Weak<TaskControlBlock>>,
+    pub children: Vec<Arc<TaskControlBlock>>,
+    /// exit code, valid once the task is a zombie
+    pub exit_code: i32,
+    /// open files, indexed by file descriptor
+    pub fd_table: Vec<Option<Arc<dyn File>>>,
+}
+
+impl TaskControlBlockInner {
+    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
+        self.trap_cx_ppn.get_mut()
+    }
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    pub fn is_zombie(&self) -> bool {
+        self.task_status == TaskStatus::Zombie
+    }
+    /// Get the lowest free file descriptor, growing the table if needed
+    ///
+    /// Fails with [`Errno::EMFILE`] once all [`MAX_FD`] are in use.
+    pub fn alloc_fd(&mut self) -> KernelResult<usize> {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            Ok(fd)
+        } else if self.fd_table.len() < MAX_FD {
+            self.fd_table.push(None);
+            Ok(self.fd_table.len() - 1)
+        } else {
+            Err(Errno::EMFILE)
+        }
+    }
+}
+
+impl TaskControlBlock {
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+    /// Set up a new task from the ELF image of app `name`
+    pub fn new(name: &str, elf_data: &[u8]) -> Result<Self, &'static str> {
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data)?;
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        // alloc a pid and a kernel stack in kernel space
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle).ok_or("out of memory")?;
+        let kernel_stack_top = kernel_stack.get_top();
+        let task_control_block = Self {
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    name: String::from(name),
+                    task_status: TaskStatus::Ready,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    task_statistics: TaskStatistics::zero_init(),
+                    sched: SchedEntity::new(),
+                    memory_set,
+                    trap_cx_ppn,
+                    base_size: user_sp,
+                    heap_bottom: user_sp,
+                    program_brk: user_sp,
+                    parent: None,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: vec![
+                        // 0 -> stdin
+                        Some(Arc::new(Stdin)),
+                        // 1 -> stdout
+                        Some(Arc::new(Stdout)),
+                        // 2 -> stderr
+                        Some(Arc::new(Stdout)),
+                    ],
+                })
+            },
+        };
+        // prepare TrapContext in user space
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            kernel_stack_top,
+            trap_handler as usize,
+        );
+        Ok(task_control_block)
+    }
+    /// Replace the address space of the task with the ELF image of app `name`.
+    ///
+    /// The task is left untouched if the image is rejected.
+    pub fn exec(&self, name: &str, elf_data: &[u8]) -> Result<(), &'static str> {
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data)?;
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        let mut inner = self.inner_exclusive_access();
+        inner.name = String::from(name);
+        // substitute memory_set, which recycles the old one
+        inner.memory_set = memory_set;
+        inner.trap_cx_ppn = trap_cx_ppn;
+        inner.base_size = user_sp;
+        inner.heap_bottom = user_sp;
+        inner.program_brk = user_sp;
+        // initialize trap_cx
+        let trap_cx = inner.get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            self.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        Ok(())
+    }
+    /// Create a child task, copying the address space, or return `None` if
+    /// frames run out
+    pub fn fork(self: &Arc<Self>) -> Option<Arc<Self>> {
+        let mut parent_inner = self.inner_exclusive_access();
+        // copy user space (including trap context)
+        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set)?;
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        // alloc a pid and a kernel stack in kernel space
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle)?;
+        let kernel_stack_top = kernel_stack.get_top();
+        let task_control_block = Arc::new(TaskControlBlock {
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    name: parent_inner.name.clone(),
+                    task_status: TaskStatus::Ready,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    task_statistics: TaskStatistics::zero_init(),
+                    // the child starts where the parent is, so that it neither
+                    // hogs the CPU nor starves under stride scheduling
+                    sched: parent_inner.sched,
+                    memory_set,
+                    trap_cx_ppn,
+                    base_size: parent_inner.base_size,
+                    heap_bottom: parent_inner.heap_bottom,
+                    program_brk: parent_inner.program_brk,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    // the child shares the open files of the parent
+                    fd_table: parent_inner.fd_table.clone(),
+                })
+            },
+        });
+        // add child
+        parent_inner.children.push(task_control_block.clone());
+        // modify kernel_sp in trap_cx
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        trap_cx.kernel_sp = kernel_stack_top;
+        Some(task_control_block)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+/// task status: UnInit, Ready, Running, Zombie, Blocked
+///
+/// A task that has exited stays a `Zombie` until its parent collects its exit
+/// code. A `Blocked` task is in no ready queue, and waits for whatever it
+/// blocked on to wake it up.
 pub enum TaskStatus {
     UnInit,
     Ready,
     Running,
-    Exited,
+    Zombie,
+    Blocked,
 }
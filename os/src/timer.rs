@@ -1,23 +1,130 @@
 //! RISC-V timer-related functionality
+//!
+//! Besides the periodic scheduling tick, the timer wakes up tasks sleeping
+//! until some deadline. Sleepers are kept in [`TIMERS`], ordered by deadline,
+//! and the timer is always programmed for the earliest of the next tick and
+//! the next wakeup.
 
-use crate::config::CLOCK_FREQ;
+use crate::board::board;
 use crate::sbi::set_timer;
+use crate::sync::UPSafeCell;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MICRO_PER_SEC: usize = 1_000_000;
+const NANO_PER_SEC: usize = 1_000_000_000;
 
 /// read the `mtime` register
 pub fn get_time() -> usize {
     time::read()
 }
 
+/// frequency of the `time` CSR, in Hz
+fn clock_freq() -> usize {
+    board().clock_freq
+}
+
 /// get current time in microseconds
 pub fn get_time_us() -> usize {
-    time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
+    // timebases need not be a multiple of 1 MHz, or even above it
+    (time::read() as u128 * MICRO_PER_SEC as u128 / clock_freq() as u128) as usize
+}
+
+/// convert a duration to `mtime` cycles, saturating on overflow
+pub fn duration_to_cycles(sec: usize, nsec: usize) -> usize {
+    sec.saturating_mul(clock_freq())
+        .saturating_add(nsec * clock_freq() / NANO_PER_SEC)
+}
+
+/// A task sleeping until `expire`, in `mtime` cycles
+pub struct TimerCondVar {
+    pub expire: usize,
+    pub task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire == other.expire
+    }
+}
+
+impl Eq for TimerCondVar {}
+
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerCondVar {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, so that the earliest deadline is on top of the max-heap
+        other.expire.cmp(&self.expire)
+    }
+}
+
+lazy_static! {
+    /// sleeping tasks, earliest deadline first
+    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
+        unsafe { UPSafeCell::new(BinaryHeap::new()) };
+    /// when the next scheduling tick is due, in `mtime` cycles
+    static ref NEXT_TICK: UPSafeCell<usize> = unsafe { UPSafeCell::new(0) };
+}
+
+/// program the timer for the earliest of the next tick and the next wakeup
+fn program_timer() {
+    let next_tick = *NEXT_TICK.exclusive_access();
+    let next = match TIMERS.exclusive_access().peek() {
+        Some(timer) => timer.expire.min(next_tick),
+        None => next_tick,
+    };
+    set_timer(next);
 }
 
 /// set the next timer interrupt
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    *NEXT_TICK.exclusive_access() = get_time() + (clock_freq() / TICKS_PER_SEC).max(1);
+    program_timer();
+}
+
+/// Wake `task` up once `mtime` reaches `expire`.
+///
+/// The caller is expected to block the task right after.
+pub fn add_timer(expire: usize, task: Arc<TaskControlBlock>) {
+    TIMERS
+        .exclusive_access()
+        .push(TimerCondVar { expire, task });
+    program_timer();
+}
+
+/// Wake up all tasks whose deadline has passed
+pub fn check_timer() {
+    let now = get_time();
+    let mut timers = TIMERS.exclusive_access();
+    while let Some(timer) = timers.peek() {
+        if timer.expire > now {
+            break;
+        }
+        wakeup_task(timers.pop().unwrap().task);
+    }
+}
+
+/// Handle a timer interrupt, returning whether a scheduling tick has passed.
+///
+/// The interrupt may be for a wakeup only, in which case the running task
+/// keeps its time slice.
+pub fn handle_timer_interrupt() -> bool {
+    check_timer();
+    let ticked = get_time() >= *NEXT_TICK.exclusive_access();
+    if ticked {
+        set_next_trigger();
+    } else {
+        program_timer();
+    }
+    ticked
 }
@@ -4,23 +4,41 @@ use riscv::register::sstatus::{self, Sstatus, SPP};
 
 #[repr(C)]
 /// trap context structure containing sstatus, sepc and registers
+///
+/// It lives in the `TRAP_CONTEXT` page of each user address space, and also
+/// records what `__alltraps` needs to get back into the kernel.
 pub struct TrapContext {
     pub x: [usize; 32],
     pub sstatus: Sstatus,
     pub sepc: usize,
+    /// token of the kernel address space
+    pub kernel_satp: usize,
+    /// kernel stack top of the task
+    pub kernel_sp: usize,
+    /// address of `trap_handler` in kernel space
+    pub trap_handler: usize,
 }
 
 impl TrapContext {
     pub fn set_sp(&mut self, sp: usize) {
         self.x[2] = sp;
     }
-    pub fn app_init_context(entry: usize, sp: usize) -> Self {
+    pub fn app_init_context(
+        entry: usize,
+        sp: usize,
+        kernel_satp: usize,
+        kernel_sp: usize,
+        trap_handler: usize,
+    ) -> Self {
         let mut sstatus = sstatus::read();
         sstatus.set_spp(SPP::User);
         let mut cx = Self {
             x: [0; 32],
             sstatus,
             sepc: entry,
+            kernel_satp,
+            kernel_sp,
+            trap_handler,
         };
         cx.set_sp(sp);
         cx
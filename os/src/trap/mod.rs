@@ -1,7 +1,8 @@
 //! Trap handling functionality
 //!
-//! For rCore, we have a single trap entry point, namely `__alltraps`. At
-//! initialization in [`init()`], we set the `stvec` CSR to point to it.
+//! For rCore, we have a single trap entry point, namely `__alltraps`. Whenever
+//! we go back to user space in [`trap_return()`], we set the `stvec` CSR to
+//! point to it.
 //!
 //! All traps go through `__alltraps`, which is defined in `trap.S`. The
 //! assembly language code does just enough work restore the kernel space
@@ -9,29 +10,59 @@
 //! [`trap_handler()`].
 //!
 //! It then calls different functionality based on what exactly the exception
-//! was. For example, timer interrupts trigger task preemption, and syscalls go
-//! to [`syscall()`].
+//! was. For example, timer interrupts trigger task preemption, external
+//! interrupts go to device drivers, and syscalls go to [`syscall()`].
+//!
+//! `__alltraps` and `__restore` live in the trampoline page, which is mapped
+//! at the same virtual address ([`TRAMPOLINE`]) in every address space, so
+//! that they keep running while `satp` is being switched.
+//!
+//! While in the kernel, `stvec` points to `__alltraps_k` instead, which saves
+//! registers on the current kernel stack and calls [`trap_from_kernel()`].
+//! The kernel only enables interrupts while idle, so these are interrupts
+//! that arrive when no task is ready to run.
 
 mod context;
 
+use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::drivers::handle_external_interrupt;
 use crate::syscall::syscall;
-use crate::task::{exit_current_and_run_next, suspend_current_and_run_next};
-use crate::timer::set_next_trigger;
+use crate::task::{
+    current_trap_cx, current_user_token, kill_current_and_run_next, tick_current_task,
+};
+use crate::timer::handle_timer_interrupt;
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sepc, sie, sscratch, stval, stvec,
 };
 
 core::arch::global_asm!(include_str!("trap.S"));
 
 /// initialize CSR `stvec` as the entry of `__alltraps`
 pub fn init() {
+    set_kernel_trap_entry();
+}
+
+/// Traps from the kernel go to `__alltraps_k`, which calls the handler in
+/// `sscratch`
+///
+/// `sscratch` is free to use here, as `__restore` sets it up again before
+/// going back to user space.
+fn set_kernel_trap_entry() {
     extern "C" {
-        fn __alltraps();
+        fn __alltraps_k();
     }
     unsafe {
-        stvec::write(__alltraps as usize, TrapMode::Direct);
+        stvec::write(__alltraps_k as usize, TrapMode::Direct);
+        sscratch::write(trap_from_kernel as usize);
+    }
+}
+
+/// Traps from user space go to `__alltraps` in the trampoline
+fn set_user_trap_entry() {
+    unsafe {
+        stvec::write(TRAMPOLINE, TrapMode::Direct);
     }
 }
 
@@ -44,25 +75,52 @@ pub fn enable_timer_interrupt() {
 
 #[no_mangle]
 /// handle an interrupt, exception, or system call from user space
-pub fn trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
+pub fn trap_handler() -> ! {
+    set_kernel_trap_entry();
     let scause = scause::read(); // get trap cause
     let stval = stval::read(); // get extra value
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
+            // jump to next instruction anyway
+            let mut cx = current_trap_cx();
             cx.sepc += 4;
-            cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
+            // get system call return value
+            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            // cx is changed during sys_exec, so we have to call it again
+            cx = current_trap_cx();
+            cx.x[10] = result as usize;
         }
-        Trap::Exception(Exception::StoreFault) | Trap::Exception(Exception::StorePageFault) => {
-            error!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, cx.sepc);
-            exit_current_and_run_next();
+        Trap::Exception(Exception::StoreFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+        | Trap::Exception(Exception::LoadFault)
+        | Trap::Exception(Exception::LoadPageFault) => {
+            error!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, current_trap_cx().sepc);
+            // page fault exit code
+            kill_current_and_run_next(-2);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             error!("[kernel] IllegalInstruction in application, core dumped.");
-            exit_current_and_run_next();
+            // illegal instruction exit code
+            kill_current_and_run_next(-3);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            suspend_current_and_run_next();
+            if handle_timer_interrupt() {
+                tick_current_task();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
+        }
+        Trap::Exception(e) => {
+            error!(
+                "[kernel] {:?} in application, stval = {:#x}, bad instruction = {:#x}, core dumped.",
+                e,
+                stval,
+                current_trap_cx().sepc
+            );
+            kill_current_and_run_next(-3);
         }
         _ => {
             panic!(
@@ -72,7 +130,58 @@ pub fn trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
             );
         }
     }
-    cx
+    trap_return();
+}
+
+#[no_mangle]
+/// set the new addr of `__restore` asm function in TRAMPOLINE page,
+/// set the reg `a0 = trap_cx_ptr, reg a1 = phy addr of usr page table`,
+/// finally, jump to new addr of `__restore` asm function
+pub fn trap_return() -> ! {
+    set_user_trap_entry();
+    let trap_cx_ptr = TRAP_CONTEXT;
+    let user_satp = current_user_token();
+    extern "C" {
+        fn __alltraps();
+        fn __restore();
+    }
+    let restore_va = __restore as usize - __alltraps as usize + TRAMPOLINE;
+    unsafe {
+        core::arch::asm!(
+            "fence.i",
+            "jr {restore_va}",
+            restore_va = in(reg) restore_va,
+            in("a0") trap_cx_ptr,
+            in("a1") user_satp,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+/// handle an interrupt from kernel mode
+///
+/// Registers saved by `__alltraps_k` are restored once this returns.
+/// Exceptions in the kernel are bugs.
+pub fn trap_from_kernel() {
+    let scause = scause::read();
+    match scause.cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            // nothing is running, so there is no time slice to account
+            handle_timer_interrupt();
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
+        }
+        _ => {
+            panic!(
+                "Unsupported trap {:?} from kernel, stval = {:#x}, sepc = {:#x}!",
+                scause.cause(),
+                stval::read(),
+                sepc::read()
+            );
+        }
+    }
 }
 
 pub use context::TrapContext;
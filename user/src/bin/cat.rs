@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{read, write};
+
+/// Copy stdin to stdout until end of file
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(0, &mut buf);
+        if len < 0 {
+            println!("cat: read failed with {}", len);
+            return -1;
+        }
+        if len == 0 {
+            return 0;
+        }
+        if write(1, &buf[..len as usize]) != len {
+            return -1;
+        }
+    }
+}
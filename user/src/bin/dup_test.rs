@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, dup, dup3, exec, fork, open, pipe, read, waitpid, write, OpenFlags};
+
+const FILE: &str = "dup_test_file\0";
+const CONTENT: &[u8] = b"rewired through dup3\n";
+const EBADF: isize = -9;
+const EINVAL: isize = -22;
+
+/// Write through a duplicate of stdout, check bad arguments, then feed a file
+/// to `cat` as its stdin and collect its stdout through a pipe
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = dup(1);
+    assert!(fd > 2);
+    assert_eq!(write(fd as usize, b"written through dup\n"), 20);
+    assert_eq!(close(fd as usize), 0);
+    assert_eq!(dup(fd as usize), EBADF);
+    assert_eq!(dup3(1, 1, 0), EINVAL);
+    assert_eq!(dup3(fd as usize, 5, 0), EBADF);
+
+    let fd = open(
+        FILE,
+        OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, CONTENT), CONTENT.len() as isize);
+    close(fd as usize);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        // child: stdin from the file, stdout into the pipe
+        let fd = open(FILE, OpenFlags::RDONLY);
+        assert!(fd > 0);
+        assert_eq!(dup3(fd as usize, 0, 0), 0);
+        assert_eq!(dup3(pipe_fd[1], 1, 0), 1);
+        close(fd as usize);
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+        exec("cat\0");
+        panic!("exec cat failed");
+    }
+    close(pipe_fd[1]);
+    let mut buf = [0u8; 64];
+    let mut len = 0;
+    loop {
+        let n = read(pipe_fd[0], &mut buf[len..]);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        len += n as usize;
+    }
+    close(pipe_fd[0]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(&buf[..len], CONTENT);
+    println!("dup_test passed!");
+    0
+}
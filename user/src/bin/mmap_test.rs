@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, munmap, waitpid, ProtFlags};
+
+const PAGE_SIZE: usize = 4096;
+/// far away from the image and the stack
+const START: usize = 0x1000_0000;
+const EINVAL: isize = -22;
+
+/// Write every byte of `[start, start + len)`, then read it back
+fn fill_and_check(start: usize, len: usize) {
+    let bytes = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, len) };
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        assert_eq!(*byte, 0);
+        *byte = i as u8;
+    }
+    for (i, byte) in bytes.iter().enumerate() {
+        assert_eq!(*byte, i as u8);
+    }
+}
+
+/// Map, use and unmap anonymous memory, checking that bad requests fail, and
+/// that a fork gets its own copy of the mappings
+#[no_mangle]
+pub fn main() -> i32 {
+    let rw = ProtFlags::READ | ProtFlags::WRITE;
+    assert_eq!(mmap(START, 3 * PAGE_SIZE, rw), START as isize);
+    fill_and_check(START, 3 * PAGE_SIZE);
+
+    // misaligned, empty, overlapping, and write-only requests
+    assert_eq!(mmap(START + 1, PAGE_SIZE, rw), EINVAL);
+    assert_eq!(mmap(START + 8 * PAGE_SIZE, 0, rw), EINVAL);
+    assert_eq!(mmap(START + 2 * PAGE_SIZE, 2 * PAGE_SIZE, rw), EINVAL);
+    assert_eq!(
+        mmap(START + 8 * PAGE_SIZE, PAGE_SIZE, ProtFlags::WRITE),
+        EINVAL
+    );
+    assert_eq!(munmap(START + 2 * PAGE_SIZE, 2 * PAGE_SIZE), EINVAL);
+    assert_eq!(munmap(START + 1, PAGE_SIZE), EINVAL);
+
+    // unmap the middle page, leaving the others
+    assert_eq!(munmap(START + PAGE_SIZE, PAGE_SIZE), 0);
+    assert_eq!(munmap(START + PAGE_SIZE, PAGE_SIZE), EINVAL);
+    let last = unsafe { &*((START + 3 * PAGE_SIZE - 1) as *const u8) };
+    assert_eq!(*last, (3 * PAGE_SIZE - 1) as u8);
+
+    // the hole can be mapped again, and is zeroed
+    assert_eq!(
+        mmap(START + PAGE_SIZE, PAGE_SIZE, rw),
+        (START + PAGE_SIZE) as isize
+    );
+    fill_and_check(START + PAGE_SIZE, PAGE_SIZE);
+
+    // pages unmapped by a child stay mapped in the parent
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(unsafe { *((START + 1) as *const u8) }, 1);
+        assert_eq!(munmap(START, 3 * PAGE_SIZE), 0);
+        // only fresh pages can be mapped there now
+        assert_eq!(mmap(START, 3 * PAGE_SIZE, rw), START as isize);
+        fill_and_check(START, 3 * PAGE_SIZE);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { *((START + 1) as *const u8) }, 1);
+    assert_eq!(munmap(START, 3 * PAGE_SIZE), 0);
+    println!("mmap_test passed!");
+    0
+}
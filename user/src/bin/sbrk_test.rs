@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, sbrk, waitpid, ProtFlags};
+
+const PAGE_SIZE: usize = 4096;
+/// `USER_HEAP_LIMIT` of the kernel
+const HEAP_LIMIT: isize = 0x40_0000;
+const EINVAL: isize = -22;
+const ENOMEM: isize = -12;
+
+/// Grow the heap, use it, and shrink it back, checking that it cannot grow
+/// beyond its limit or shrink below its bottom
+#[no_mangle]
+pub fn main() -> i32 {
+    let bottom = sbrk(0);
+    assert!(bottom > 0);
+
+    // a single byte maps a whole page
+    assert_eq!(sbrk(1), bottom);
+    let bytes = unsafe { core::slice::from_raw_parts_mut(bottom as *mut u8, PAGE_SIZE) };
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        assert_eq!(*byte, 0);
+        *byte = i as u8;
+    }
+
+    // growing keeps what is already there
+    assert_eq!(sbrk(2 * PAGE_SIZE as isize), bottom + 1);
+    let end = bottom as usize + 2 * PAGE_SIZE + 1;
+    let last = unsafe { &mut *((end - 1) as *mut u8) };
+    assert_eq!(*last, 0);
+    *last = 42;
+    assert_eq!(unsafe { *((bottom as usize + 7) as *const u8) }, 7);
+
+    // too much, or below the bottom
+    assert_eq!(sbrk(HEAP_LIMIT), ENOMEM);
+    assert_eq!(sbrk(-(3 * PAGE_SIZE as isize)), EINVAL);
+    assert_eq!(sbrk(0), end as isize);
+
+    // the child gets a copy of the heap
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(unsafe { *((end - 1) as *const u8) }, 42);
+        assert_eq!(sbrk(-(2 * PAGE_SIZE as isize)), end as isize);
+        // the pages above the break are unmapped, so fresh ones can go there
+        let page = (end - 1) & !(PAGE_SIZE - 1);
+        let rw = ProtFlags::READ | ProtFlags::WRITE;
+        assert_eq!(mmap(page, PAGE_SIZE, rw), page as isize);
+        assert_eq!(unsafe { *((end - 1) as *const u8) }, 0);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(*last, 42);
+
+    // shrink back to the bottom, then the heap can grow again up to the limit
+    assert_eq!(sbrk(bottom - end as isize), end as isize);
+    assert_eq!(sbrk(0), bottom);
+    assert_eq!(sbrk(HEAP_LIMIT), bottom);
+    assert_eq!(sbrk(1), ENOMEM);
+    assert_eq!(sbrk(-HEAP_LIMIT), bottom + HEAP_LIMIT);
+    println!("sbrk_test passed!");
+    0
+}
@@ -0,0 +1,231 @@
+//! The shell, started by the kernel as the init process
+//!
+//! Each line is a command, like
+//!
+//! ```text
+//! >> cat < input | cat > output &
+//! ```
+//!
+//! that is, programs joined by `|`, the first one optionally reading from a
+//! file with `<`, the last one optionally writing to a file with `>`, and an
+//! optional `&` at the end to run it in the background. Programs take no
+//! arguments, as `exec` does not pass any.
+//!
+//! Being the init process, the shell also reaps orphans. Typing `exit` or
+//! Ctrl-D ends the shell, and with it the whole system.
+
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, dup3, exec, exit, fork, open, pipe, read, try_wait, waitpid, OpenFlags};
+
+const PROMPT: &str = ">> ";
+
+/// One program of a pipeline
+struct Stage {
+    /// program name, nul terminated
+    program: String,
+    input: Option<String>,
+    output: Option<String>,
+}
+
+/// Programs joined by pipes
+struct Command {
+    stages: Vec<Stage>,
+    background: bool,
+}
+
+/// Append a nul byte, for the kernel
+fn c_string(s: &str) -> String {
+    let mut s = String::from(s);
+    s.push('\0');
+    s
+}
+
+/// Parse one stage, whose tokens are separated by spaces
+fn parse_stage(text: &str) -> Result<Stage, &'static str> {
+    let mut tokens = text.split_whitespace();
+    let mut program = None;
+    let mut input = None;
+    let mut output = None;
+    while let Some(token) = tokens.next() {
+        match token {
+            "<" => input = Some(c_string(tokens.next().ok_or("missing file after <")?)),
+            ">" => output = Some(c_string(tokens.next().ok_or("missing file after >")?)),
+            _ if program.is_none() => program = Some(c_string(token)),
+            _ => return Err("programs take no arguments"),
+        }
+    }
+    Ok(Stage {
+        program: program.ok_or("missing program")?,
+        input,
+        output,
+    })
+}
+
+/// Parse a command line, or `None` if it is blank
+fn parse(line: &str) -> Result<Option<Command>, &'static str> {
+    let mut line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let background = line.ends_with('&');
+    if background {
+        line = line[..line.len() - 1].trim_end();
+    }
+    let stages = line
+        .split('|')
+        .map(parse_stage)
+        .collect::<Result<Vec<_>, _>>()?;
+    let last = stages.len() - 1;
+    for (i, stage) in stages.iter().enumerate() {
+        if i != 0 && stage.input.is_some() {
+            return Err("only the first program can read from a file");
+        }
+        if i != last && stage.output.is_some() {
+            return Err("only the last program can write to a file");
+        }
+    }
+    Ok(Some(Command { stages, background }))
+}
+
+/// Open `path` and move it to `fd`, exiting on failure
+fn redirect(path: &str, flags: OpenFlags, fd: usize) {
+    let file = open(path, flags);
+    if file < 0 {
+        println!("Shell: cannot open {}", path.trim_end_matches('\0'));
+        exit(-1);
+    }
+    dup3(file as usize, fd, 0);
+    close(file as usize);
+}
+
+/// Start every program of `command`, returning their pids
+fn spawn(command: &Command) -> Vec<usize> {
+    let count = command.stages.len();
+    let mut pipes = Vec::new();
+    for _ in 1..count {
+        let mut pipe_fd = [0usize; 2];
+        if pipe(&mut pipe_fd) < 0 {
+            println!("Shell: cannot create pipe");
+            break;
+        }
+        pipes.push(pipe_fd);
+    }
+    let mut pids = Vec::new();
+    if pipes.len() == count - 1 {
+        for (i, stage) in command.stages.iter().enumerate() {
+            let pid = fork();
+            if pid == 0 {
+                if i > 0 {
+                    dup3(pipes[i - 1][0], 0, 0);
+                }
+                if i < count - 1 {
+                    dup3(pipes[i][1], 1, 0);
+                }
+                for pipe_fd in pipes.iter() {
+                    close(pipe_fd[0]);
+                    close(pipe_fd[1]);
+                }
+                if let Some(input) = &stage.input {
+                    redirect(input, OpenFlags::RDONLY, 0);
+                }
+                if let Some(output) = &stage.output {
+                    redirect(
+                        output,
+                        OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY,
+                        1,
+                    );
+                }
+                exec(&stage.program);
+                println!(
+                    "Shell: {}: command not found",
+                    stage.program.trim_end_matches('\0')
+                );
+                exit(-1);
+            }
+            if pid < 0 {
+                println!("Shell: cannot fork");
+                break;
+            }
+            pids.push(pid as usize);
+        }
+    }
+    // the children hold their own ends, and readers only see the end of file
+    // once every write end is closed
+    for pipe_fd in pipes.iter() {
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+    }
+    pids
+}
+
+/// Reap background jobs and orphans that have exited
+fn reap() {
+    let mut exit_code = 0;
+    loop {
+        let pid = try_wait(&mut exit_code);
+        if pid <= 0 {
+            break;
+        }
+        println!("[{}] Done, exit code {}", pid, exit_code);
+    }
+}
+
+/// Read a line from the console, or `None` at the end of input
+fn read_line() -> Option<String> {
+    let mut line = Vec::new();
+    let mut buf = [0u8; 128];
+    while !line.ends_with(b"\n") {
+        let len = read(0, &mut buf);
+        if len <= 0 {
+            return None;
+        }
+        line.extend_from_slice(&buf[..len as usize]);
+    }
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("Rust user shell");
+    loop {
+        reap();
+        print!("{}", PROMPT);
+        let line = match read_line() {
+            Some(line) => line,
+            None => break,
+        };
+        if line.trim() == "exit" {
+            break;
+        }
+        let command = match parse(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(err) => {
+                println!("Shell: {}", err);
+                continue;
+            }
+        };
+        let pids = spawn(&command);
+        if command.background {
+            for pid in pids {
+                println!("[{}] Started", pid);
+            }
+            continue;
+        }
+        for pid in pids {
+            let mut exit_code = 0;
+            waitpid(pid, &mut exit_code);
+            println!("Shell: Process {} exited with code {}", pid, exit_code);
+        }
+    }
+    println!("Shell: exit");
+    0
+}
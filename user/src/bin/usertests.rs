@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, waitpid};
+
+/// tests to run, in order
+const TESTS: &[&str] = &["dup_test\0", "mmap_test\0", "sbrk_test\0"];
+
+/// Run every test in its own process, and report those that fail
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut failed = 0;
+    for test in TESTS {
+        let name = test.trim_end_matches('\0');
+        let pid = fork();
+        if pid == 0 {
+            if exec(test) < 0 {
+                println!("usertests: cannot exec {}", name);
+                return -1;
+            }
+            unreachable!();
+        }
+        let mut exit_code = 0;
+        waitpid(pid as usize, &mut exit_code);
+        if exit_code != 0 {
+            println!("usertests: {} failed with {}", name, exit_code);
+            failed += 1;
+        }
+    }
+    println!(
+        "usertests: {}/{} tests passed",
+        TESTS.len() - failed,
+        TESTS.len()
+    );
+    if failed == 0 {
+        0
+    } else {
+        -1
+    }
+}
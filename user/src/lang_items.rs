@@ -0,0 +1,21 @@
+//! The panic handler
+
+use super::exit;
+use core::panic::PanicInfo;
+
+#[panic_handler]
+/// panic handler, which exits the app with code -1
+fn panic_handler(info: &PanicInfo) -> ! {
+    let message = info.message().unwrap();
+    if let Some(location) = info.location() {
+        println!(
+            "Panicked at {}:{}, {}",
+            location.file(),
+            location.line(),
+            message
+        );
+    } else {
+        println!("Panicked: {}", message);
+    }
+    exit(-1);
+}
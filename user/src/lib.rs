@@ -0,0 +1,147 @@
+//! The user library, shared by all apps in `src/bin`
+//!
+//! It provides the entry point `_start`, which sets up a heap and calls the
+//! `main` of the app, and safe wrappers around the syscalls of the kernel.
+//!
+//! Paths given to the kernel must end with a nul byte, like `"cat\0"`.
+
+#![no_std]
+#![feature(linkage)]
+#![feature(panic_info_message)]
+#![feature(alloc_error_handler)]
+
+#[macro_use]
+pub mod console;
+mod lang_items;
+mod syscall;
+
+extern crate alloc;
+#[macro_use]
+extern crate bitflags;
+
+use buddy_system_allocator::LockedHeap;
+use syscall::*;
+
+pub use syscall::TimeVal;
+
+const USER_HEAP_SIZE: usize = 16384;
+
+/// option of `sys_waitpid`: do not block if no child has exited
+const WNOHANG: usize = 1;
+
+static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
+
+#[global_allocator]
+static HEAP: LockedHeap = LockedHeap::empty();
+
+#[alloc_error_handler]
+pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
+    panic!("Heap allocation error, layout = {:?}", layout);
+}
+
+#[no_mangle]
+#[link_section = ".text.entry"]
+pub extern "C" fn _start() -> ! {
+    unsafe {
+        HEAP.lock()
+            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
+    }
+    exit(main());
+}
+
+#[linkage = "weak"]
+#[no_mangle]
+fn main() -> i32 {
+    panic!("Cannot find main!");
+}
+
+bitflags! {
+    /// flags of [`open`]
+    pub struct OpenFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        const CREATE = 1 << 9;
+        const TRUNC = 1 << 10;
+    }
+}
+
+bitflags! {
+    /// protection of [`mmap`]
+    pub struct ProtFlags: usize {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+pub fn dup(fd: usize) -> isize {
+    sys_dup(fd)
+}
+/// Make `new_fd` refer to what `old_fd` does, closing it first if needed
+pub fn dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    sys_dup3(old_fd, new_fd, flags)
+}
+pub fn open(path: &str, flags: OpenFlags) -> isize {
+    sys_open(path, flags.bits)
+}
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+/// Create a pipe, `pipe[0]` being its read end and `pipe[1]` its write end
+pub fn pipe(pipe_fd: &mut [usize; 2]) -> isize {
+    sys_pipe(pipe_fd)
+}
+pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+    sys_read(fd, buf)
+}
+pub fn write(fd: usize, buf: &[u8]) -> isize {
+    sys_write(fd, buf)
+}
+pub fn exit(exit_code: i32) -> ! {
+    sys_exit(exit_code);
+}
+pub fn yield_() -> isize {
+    sys_yield()
+}
+/// Get the time since boot, in milliseconds
+pub fn get_time() -> isize {
+    let mut time = TimeVal::default();
+    match sys_get_time(&mut time) {
+        0 => ((time.sec & 0xffff) * 1000 + time.usec / 1000) as isize,
+        err => err,
+    }
+}
+pub fn getpid() -> isize {
+    sys_getpid()
+}
+pub fn fork() -> isize {
+    sys_fork()
+}
+pub fn exec(path: &str) -> isize {
+    sys_exec(path)
+}
+/// Map `len` bytes of zeroed memory at the page-aligned `start`, returning
+/// `start`
+pub fn mmap(start: usize, len: usize, prot: ProtFlags) -> isize {
+    sys_mmap(start, len, prot.bits)
+}
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
+}
+/// Move the program break by `increment` bytes, returning the old break
+pub fn sbrk(increment: isize) -> isize {
+    sys_sbrk(increment)
+}
+/// Wait for any child to exit, returning its pid
+pub fn wait(exit_code: &mut i32) -> isize {
+    sys_waitpid(-1, exit_code as *mut _, 0)
+}
+/// Wait for the child `pid` to exit
+pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid as isize, exit_code as *mut _, 0)
+}
+/// Reap any child that has exited, returning its pid, or 0 if none has
+pub fn try_wait(exit_code: &mut i32) -> isize {
+    sys_waitpid(-1, exit_code as *mut _, WNOHANG)
+}